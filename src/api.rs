@@ -0,0 +1,546 @@
+// 库接口：供其他程序直接调用，不经过命令行和进程退出码
+
+use anyhow::Result;
+use clap::{CommandFactory, FromArgMatches};
+use rayon::prelude::*;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::args::{
+    prepare_args, ArchiveCompression, ArchiveFormat, Args, ExtractMode, FramePattern, HwAccel,
+    OnCollision, OnSuccess, OnUnknown, OutputFormat, PictType, PreviewFormat, Quality, Schedule,
+    SheetLayout, ShellArgs, SortKey, Tonemap,
+};
+use crate::batch::Batch;
+use crate::discovery::{file_id, is_url, scan_root, sort_videos, PathFilters};
+use crate::error::FailureKind;
+use crate::extract::process_video;
+use crate::output::{resolve_collisions, OutputPlan};
+use crate::probe::MediaCache;
+use crate::state::StateIndex;
+
+/// 提取选项：命令行中影响查找、筛选和提取视频的参数，字段含义与同名的命令行选项相同 (见 `vid --help`)。
+/// 日志、进度、报告和试运行等只属于命令行的参数不在其中。
+/// `Default` 与不带任何参数的命令行相同，可以用 from_args 按命令行写法构造，或在默认值上链式设置
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub(crate) input: Vec<PathBuf>,
+    pub(crate) input_list: Option<PathBuf>,
+    pub(crate) no_ext_filter: bool,
+    pub(crate) output: PathBuf,
+    pub(crate) threads: usize,
+    pub(crate) ffmpeg_threads: Option<usize>,
+    pub(crate) segment_parallel: Option<u32>,
+    pub(crate) segment_min_secs: f64,
+    pub(crate) flatten: bool,
+    pub(crate) on_collision: OnCollision,
+    pub(crate) mode: ExtractMode,
+    pub(crate) pict_types: Vec<PictType>,
+    pub(crate) idr_only: bool,
+    pub(crate) fast_seek: bool,
+    pub(crate) scene_threshold: f64,
+    pub(crate) interval_secs: f64,
+    pub(crate) positions: Option<Vec<f64>>,
+    pub(crate) poster_only: bool,
+    pub(crate) poster_position: f64,
+    pub(crate) positions_count: Option<u32>,
+    pub(crate) start: Option<f64>,
+    pub(crate) end: Option<f64>,
+    pub(crate) max_frames: Option<u64>,
+    pub(crate) every: Option<u64>,
+    pub(crate) max_width: Option<u32>,
+    pub(crate) max_height: Option<u32>,
+    pub(crate) timestamp_names: bool,
+    pub(crate) pattern: Option<FramePattern>,
+    pub(crate) dedup: bool,
+    pub(crate) dedup_threshold: u32,
+    pub(crate) dedup_filter: bool,
+    pub(crate) min_sharpness: Option<f64>,
+    pub(crate) sharpness_report: bool,
+    pub(crate) skip_black: bool,
+    pub(crate) black_threshold: u8,
+    pub(crate) black_ratio: f64,
+    pub(crate) contact_sheet: Option<SheetLayout>,
+    pub(crate) sheet_only: bool,
+    pub(crate) sheet_tile_width: u32,
+    pub(crate) sheet_max_frames: Option<usize>,
+    pub(crate) bookends: bool,
+    pub(crate) preview: Option<PreviewFormat>,
+    pub(crate) preview_width: u32,
+    pub(crate) preview_fps: u32,
+    pub(crate) timestamps: bool,
+    pub(crate) scale: Option<(u32, u32)>,
+    pub(crate) quality: Quality,
+    pub(crate) format: OutputFormat,
+    pub(crate) archive: Option<ArchiveFormat>,
+    pub(crate) archive_compression: Option<ArchiveCompression>,
+    pub(crate) extensions: String,
+    pub(crate) hwaccel: HwAccel,
+    pub(crate) no_autorotate: bool,
+    pub(crate) tonemap: Tonemap,
+    pub(crate) ffmpeg_path: PathBuf,
+    pub(crate) ffprobe_path: Option<PathBuf>,
+    pub(crate) retries: u32,
+    pub(crate) timeout: Option<u64>,
+    pub(crate) min_duration: Option<f64>,
+    pub(crate) max_duration: Option<f64>,
+    pub(crate) min_height: Option<u32>,
+    pub(crate) min_size: Option<u64>,
+    pub(crate) max_size: Option<u64>,
+    pub(crate) max_output_size: Option<u64>,
+    pub(crate) on_unknown: OnUnknown,
+    pub(crate) min_age: Option<u64>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) follow_symlinks: bool,
+    pub(crate) skip_hidden: bool,
+    pub(crate) include: Vec<String>,
+    pub(crate) exclude: Vec<String>,
+    pub(crate) sort: SortKey,
+    pub(crate) schedule: Schedule,
+    pub(crate) ffmpeg_input_args: Option<ShellArgs>,
+    pub(crate) ffmpeg_output_args: Option<ShellArgs>,
+    pub(crate) keep_going: bool,
+    pub(crate) on_success: OnSuccess,
+    pub(crate) log_dir: Option<PathBuf>,
+    pub(crate) keep_logs: bool,
+    pub(crate) overwrite: bool,
+    pub(crate) force: bool,
+    pub(crate) state_file: Option<PathBuf>,
+    pub(crate) rescan: bool,
+    pub(crate) no_manifest: bool,
+}
+
+// 命令行参数经 prepare_args 换算和校验后，取出库代码使用的部分
+impl From<&Args> for ExtractOptions {
+    fn from(args: &Args) -> Self {
+        ExtractOptions {
+            input: args.input.clone(),
+            input_list: args.input_list.clone(),
+            no_ext_filter: args.no_ext_filter,
+            output: args.output.clone(),
+            threads: args.threads,
+            ffmpeg_threads: args.ffmpeg_threads,
+            segment_parallel: args.segment_parallel,
+            segment_min_secs: args.segment_min_secs,
+            flatten: args.flatten,
+            on_collision: args.on_collision,
+            mode: args.mode,
+            pict_types: args.pict_types.clone(),
+            idr_only: args.idr_only,
+            fast_seek: args.fast_seek,
+            scene_threshold: args.scene_threshold,
+            interval_secs: args.interval_secs,
+            positions: args.positions.clone(),
+            poster_only: args.poster_only,
+            poster_position: args.poster_position,
+            positions_count: args.positions_count,
+            start: args.start,
+            end: args.end,
+            max_frames: args.max_frames,
+            every: args.every,
+            max_width: args.max_width,
+            max_height: args.max_height,
+            timestamp_names: args.timestamp_names,
+            pattern: args.pattern.clone(),
+            dedup: args.dedup,
+            dedup_threshold: args.dedup_threshold,
+            dedup_filter: args.dedup_filter,
+            min_sharpness: args.min_sharpness,
+            sharpness_report: args.sharpness_report,
+            skip_black: args.skip_black,
+            black_threshold: args.black_threshold,
+            black_ratio: args.black_ratio,
+            contact_sheet: args.contact_sheet,
+            sheet_only: args.sheet_only,
+            sheet_tile_width: args.sheet_tile_width,
+            sheet_max_frames: args.sheet_max_frames,
+            bookends: args.bookends,
+            preview: args.preview,
+            preview_width: args.preview_width,
+            preview_fps: args.preview_fps,
+            timestamps: args.timestamps,
+            scale: args.scale,
+            quality: args.quality.clone(),
+            format: args.format,
+            archive: args.archive,
+            archive_compression: args.archive_compression,
+            extensions: args.extensions.clone(),
+            hwaccel: args.hwaccel,
+            no_autorotate: args.no_autorotate,
+            tonemap: args.tonemap,
+            ffmpeg_path: args.ffmpeg_path.clone(),
+            ffprobe_path: args.ffprobe_path.clone(),
+            retries: args.retries,
+            timeout: args.timeout,
+            min_duration: args.min_duration,
+            max_duration: args.max_duration,
+            min_height: args.min_height,
+            min_size: args.min_size,
+            max_size: args.max_size,
+            max_output_size: args.max_output_size,
+            on_unknown: args.on_unknown,
+            min_age: args.min_age,
+            max_depth: args.max_depth,
+            follow_symlinks: args.follow_symlinks,
+            skip_hidden: args.skip_hidden,
+            include: args.include.clone(),
+            exclude: args.exclude.clone(),
+            sort: args.sort,
+            schedule: args.schedule,
+            ffmpeg_input_args: args.ffmpeg_input_args.clone(),
+            ffmpeg_output_args: args.ffmpeg_output_args.clone(),
+            keep_going: args.keep_going,
+            on_success: args.on_success.clone(),
+            log_dir: args.log_dir.clone(),
+            keep_logs: args.keep_logs,
+            overwrite: args.overwrite,
+            force: args.force,
+            state_file: args.state_file.clone(),
+            rescan: args.rescan,
+            no_manifest: args.no_manifest,
+        }
+    }
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions::from_args(std::iter::empty::<OsString>()).expect("默认选项总是有效")
+    }
+}
+
+// 测试用：按命令行写法构造选项 (不含程序名和 --input/--output)，并做与命令行相同的换算和校验。
+// ffmpeg 指向不存在的路径，测试中不会真的调用 ffprobe
+#[cfg(test)]
+pub(crate) fn test_args(options: &[&str]) -> ExtractOptions {
+    use clap::Parser;
+
+    let base = ["vid", "-i", "/videos", "-o", "/out", "--ffmpeg-path", "/nonexistent/ffmpeg"];
+    let mut args = Args::try_parse_from(base.iter().chain(options)).unwrap();
+    prepare_args(&mut args).unwrap();
+    ExtractOptions::from(&args)
+}
+
+impl ExtractOptions {
+    /// 按命令行参数的写法构造选项 (不含程序名)，如 `["--output", "frames", "--format", "png"]`。
+    /// 不读取配置文件；--input 可以省略，此时输出目录只按文件名命名，与使用路径列表时相同
+    pub fn from_args<I, T>(options: I) -> Result<Self, VidError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let command = Args::command()
+            .mut_arg("input", |arg| arg.required_unless_present(clap::builder::Resettable::Reset));
+        let cli = std::iter::once(OsString::from("vid")).chain(options.into_iter().map(Into::into));
+        let matches = command
+            .try_get_matches_from(cli)
+            .map_err(|e| VidError::InvalidOptions(e.to_string()))?;
+        let mut args =
+            Args::from_arg_matches(&matches).map_err(|e| VidError::InvalidOptions(e.to_string()))?;
+        prepare_args(&mut args).map_err(|e| VidError::InvalidOptions(format!("{:#}", e)))?;
+        Ok(ExtractOptions::from(&args))
+    }
+
+    /// 输出目录 (--output)
+    pub fn output(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output = dir.into();
+        self
+    }
+
+    /// 同时处理的视频数 (--threads)，0 表示使用全部逻辑核心
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = if threads == 0 { num_cpus::get() } else { threads };
+        self
+    }
+
+    /// FFmpeg可执行文件路径 (--ffmpeg-path)
+    pub fn ffmpeg_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ffmpeg_path = path.into();
+        self
+    }
+
+    /// ffprobe可执行文件路径 (--ffprobe-path)，未设置时取FFmpeg同目录下的 ffprobe
+    pub fn ffprobe_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ffprobe_path = Some(path.into());
+        self
+    }
+
+    /// 状态文件 (--state-file)，rescan 对应 --rescan
+    pub fn state_file(mut self, path: impl Into<PathBuf>, rescan: bool) -> Self {
+        self.state_file = Some(path.into());
+        self.rescan = rescan;
+        self
+    }
+
+    /// 输出已存在时清除其中的关键帧后重新提取 (--overwrite)
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+}
+
+/// 单个视频的提取结果
+#[derive(Debug, Clone)]
+pub struct ExtractionResult {
+    pub source: PathBuf,
+    pub output_dir: PathBuf,
+    /// 输出目录中的帧数 (跳过时为已有的帧数)
+    pub frame_count: usize,
+    /// 输出已存在而未重新提取
+    pub skipped: bool,
+    /// 提取过程中 ffprobe 读取的视频时长 (秒)，跳过或无法获取时为 None
+    pub duration: Option<f64>,
+    /// FFmpeg调用的尝试次数 (含 --retries 重试)，跳过时为0
+    pub attempts: u32,
+    pub elapsed: Duration,
+}
+
+/// run_batch 的进度事件，可能在多个线程中同时回调
+#[derive(Debug)]
+pub enum Event<'a> {
+    Started(&'a Path),
+    Finished(&'a ExtractionResult),
+    Failed(&'a Path, &'a VidError),
+}
+
+/// 库接口的错误类型，便于调用方按原因分别处理
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VidError {
+    /// 无法启动FFmpeg或ffprobe
+    Spawn(std::io::Error),
+    /// FFmpeg返回非零状态，stderr 为其最后几行输出
+    Ffmpeg { status: ExitStatus, stderr: String },
+    /// 路径不存在或不是文件/目录
+    InvalidPath(PathBuf),
+    /// 读写文件或目录失败
+    Io(std::io::Error),
+    /// 选项无法解析或互相冲突
+    InvalidOptions(String),
+    /// 其他错误，如超时、无效的视频等
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for VidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VidError::Spawn(e) => write!(f, "执行FFmpeg命令失败: {}", e),
+            VidError::Ffmpeg { status, stderr } if stderr.is_empty() => {
+                write!(f, "FFmpeg返回错误状态: {}", status)
+            }
+            VidError::Ffmpeg { status, stderr } => {
+                write!(f, "FFmpeg返回错误状态: {} ({})", status, stderr)
+            }
+            VidError::InvalidPath(path) => write!(f, "无效的路径: {:?}", path),
+            VidError::Io(e) => write!(f, "{}", e),
+            VidError::InvalidOptions(message) => write!(f, "无效的选项: {}", message),
+            VidError::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for VidError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VidError::Spawn(e) | VidError::Io(e) => Some(e),
+            VidError::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// 按内部错误链中的 FailureKind 和 io::Error 归类；io::Error 的消息带上完整的错误链，保留出错的路径
+impl From<anyhow::Error> for VidError {
+    fn from(err: anyhow::Error) -> Self {
+        let io = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<std::io::Error>())
+            .map(|e| e.kind());
+        match (err.downcast_ref::<FailureKind>(), io) {
+            (Some(FailureKind::FfmpegStatus(status, tail)), _) => VidError::Ffmpeg {
+                status: *status,
+                stderr: tail.join("\n"),
+            },
+            (Some(FailureKind::Spawn), Some(kind)) => {
+                VidError::Spawn(std::io::Error::new(kind, format!("{:#}", err)))
+            }
+            (_, Some(kind)) => VidError::Io(std::io::Error::new(kind, format!("{:#}", err))),
+            _ => VidError::Other(err),
+        }
+    }
+}
+
+/// 列出 root 下符合选项 (扩展名、--include/--exclude、--max-depth 等) 的视频并按 --sort 排序；
+/// root 是文件时直接返回它
+pub fn discover_videos(root: &Path, options: &ExtractOptions) -> Result<Vec<PathBuf>, VidError> {
+    if root.is_file() {
+        return Ok(vec![root.to_path_buf()]);
+    }
+    if !root.is_dir() {
+        return Err(VidError::InvalidPath(root.to_path_buf()));
+    }
+    let filters = PathFilters::from_args(options)?;
+    let cache = MediaCache::default();
+    let mut scan_errors = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    let mut video_paths: Vec<PathBuf> = scan_root(root, options, &filters, &cache, &mut scan_errors)
+        .into_iter()
+        .filter(|path| seen.insert(file_id(path, &cache)))
+        .collect();
+    for scan_error in &scan_errors {
+        warn!("无法访问: {}: {}", scan_error.path.display(), scan_error.reason);
+    }
+    sort_videos(&mut video_paths, options.sort, &cache);
+    Ok(video_paths)
+}
+
+/// 提取单个视频的关键帧，行为与命令行处理一个视频相同；指定 --state-file 时按其记录跳过，
+/// 提取后更新状态文件
+pub fn extract_keyframes(
+    video_path: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractionResult, VidError> {
+    let state = load_state(options)?;
+    let (plan, cache) = (OutputPlan::default(), MediaCache::default());
+    let batch = Batch { plan: &plan, cache: &cache, state: state.as_ref() };
+    let result = extract_planned(video_path, options, batch);
+    if let Some(state) = &state {
+        state.save()?;
+    }
+    result
+}
+
+// --state-file 对应的索引，未指定时为 None
+fn load_state(options: &ExtractOptions) -> Result<Option<StateIndex>, VidError> {
+    let state = options.state_file.as_deref().map(|path| StateIndex::load(path, options.rescan));
+    Ok(state.transpose()?)
+}
+
+// 按 run_batch 分配的输出目录提取单个视频
+fn extract_planned(
+    video_path: &Path,
+    options: &ExtractOptions,
+    batch: Batch<'_>,
+) -> Result<ExtractionResult, VidError> {
+    if !video_path.is_file() && !is_url(video_path) {
+        return Err(VidError::InvalidPath(video_path.to_path_buf()));
+    }
+    let started = Instant::now();
+    let result = process_video(video_path, options, batch)?;
+    Ok(ExtractionResult {
+        source: result.source,
+        output_dir: result.output_dir,
+        frame_count: result.frame_count,
+        skipped: result.skipped,
+        duration: result.duration,
+        attempts: result.attempts,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// 以 --threads 个线程并行提取一批视频，按输入顺序返回每个视频的结果；
+/// 失败的视频不影响其他视频 (相当于总是 --keep-going)，每个视频开始和结束时回调 on_event。
+/// 输出目录冲突按 --on-collision 处理，与命令行相同：error 时整批报错，
+/// skip 时被跳过的视频返回 VidError::Other 且不回调。指定 --state-file 时整批结束后写入一次状态文件
+pub fn run_batch<F>(
+    video_paths: &[PathBuf],
+    options: &ExtractOptions,
+    on_event: F,
+) -> Result<Vec<Result<ExtractionResult, VidError>>, VidError>
+where
+    F: Fn(Event<'_>) + Sync,
+{
+    let mut plan = OutputPlan::default();
+    let (_, collided) = resolve_collisions(video_paths.to_vec(), options, &mut plan)?;
+    let cache = MediaCache::default();
+    let state = load_state(options)?;
+    let batch = Batch { plan: &plan, cache: &cache, state: state.as_ref() };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()
+        .map_err(|e| VidError::Other(e.into()))?;
+    let results = pool.install(|| {
+        video_paths
+            .par_iter()
+            .map(|video_path| {
+                if let Some(skipped) = collided.iter().find(|c| &c.path == video_path) {
+                    let reason = anyhow::anyhow!("{}: {}", video_path.display(), skipped.reason);
+                    return Err(VidError::Other(reason));
+                }
+                on_event(Event::Started(video_path));
+                let result = extract_planned(video_path, options, batch);
+                match &result {
+                    Ok(extraction) => on_event(Event::Finished(extraction)),
+                    Err(e) => on_event(Event::Failed(video_path, e)),
+                }
+                result
+            })
+            .collect()
+    });
+    if let Some(state) = &state {
+        state.save()?;
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::reset_dir;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn run_batch_resolves_collisions() {
+        let options = |policy: &str| {
+            ExtractOptions::from_args(["--output", "/out", "--flatten", "--on-collision", policy])
+                .unwrap()
+        };
+        let videos = [
+            PathBuf::from("/videos/cam1/clip.mp4"),
+            PathBuf::from("/videos/cam2/clip.mp4"),
+        ];
+        let started = AtomicUsize::new(0);
+        let results = run_batch(&videos, &options("skip"), |event| {
+            if let Event::Started(_) = event {
+                started.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .unwrap();
+        assert!(matches!(results[0], Err(VidError::InvalidPath(_))));
+        assert!(matches!(&results[1], Err(VidError::Other(e)) if e.to_string().contains("冲突")));
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+
+        assert!(run_batch(&videos, &options("error"), |_| {}).is_err());
+    }
+
+    #[test]
+    fn library_calls_honour_the_state_file() {
+        let dir = std::env::temp_dir().join(format!("vid-state-{}", std::process::id()));
+        reset_dir(&dir).unwrap();
+        let video = dir.join("clip.mp4");
+        std::fs::write(&video, b"video").unwrap();
+        let state_file = dir.join("state.json");
+        let state = StateIndex::load(&state_file, false).unwrap();
+        state.record(&video, 7).unwrap();
+        state.save().unwrap();
+
+        // 状态文件记录与源文件一致时直接跳过，不调用 (不存在的) FFmpeg
+        let options = ExtractOptions::default()
+            .output(dir.join("out"))
+            .ffmpeg_path("/nonexistent/ffmpeg")
+            .state_file(&state_file, false);
+        let result = extract_keyframes(&video, &options).unwrap();
+        assert!(result.skipped);
+        assert_eq!(result.frame_count, 7);
+        let results = run_batch(std::slice::from_ref(&video), &options, |_| {}).unwrap();
+        assert!(results[0].as_ref().is_ok_and(|r| r.skipped));
+
+        // 源文件变化后重新提取
+        std::fs::write(&video, b"changed video").unwrap();
+        assert!(extract_keyframes(&video, &options).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1206 @@
+// 命令行参数的定义、取值类型和解析，以及命令行与库接口共用的换算和校验
+
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::discovery::get_video_extensions;
+
+// 命令行参数结构
+#[derive(Parser, Debug, Clone)]
+#[command(
+    version,
+    about = "视频关键帧批量提取工具",
+    after_help = "退出码: 0 全部成功；1 有视频处理失败；2 参数错误或无法运行 (如找不到FFmpeg)；\
+                  3 --dry-run 时没有需要处理的视频；4 输出超过 --max-output-size 后仍有视频未处理；130 被 Ctrl-C 中断"
+)]
+pub(crate) struct Args {
+    /// 配置文件路径，其中的选项作为默认值，命令行参数优先 (默认读取当前目录下的 vid.toml)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// 输入目录路径，可重复指定以一次处理多个目录 (此时输出为 <输出目录>/<输入目录名>/<相对路径>)；也可以是单个视频文件 (不检查扩展名)；为 - 时从标准输入读取视频路径列表 (格式同 --input-list)
+    #[arg(short, long, required_unless_present = "input_list", conflicts_with = "input_list")]
+    pub(crate) input: Vec<PathBuf>,
+
+    /// 从文本文件读取视频路径列表 (- 表示标准输入)，每行一个，忽略空行和 # 开头的注释行，不再扫描目录；http:// 或 https:// 开头的行直接交给 FFmpeg 打开，输出目录名取 URL 最后一段路径
+    #[arg(long, visible_alias = "from-list")]
+    pub(crate) input_list: Option<PathBuf>,
+
+    /// 使用路径列表时不按 --extensions 过滤
+    #[arg(long)]
+    pub(crate) no_ext_filter: bool,
+
+    /// 输出目录路径
+    #[arg(short, long, default_value = "./keyframes_output")]
+    pub(crate) output: PathBuf,
+
+    /// 同时处理的视频数 (即同时运行的ffmpeg进程数)，0 表示使用全部逻辑核心
+    #[arg(short, long, visible_alias = "jobs", default_value_t = num_cpus::get())]
+    pub(crate) threads: usize,
+
+    /// 每个ffmpeg进程的 -threads 值，0表示由ffmpeg自行决定
+    ///
+    /// 总线程数约为 --threads × --ffmpeg-threads；默认取 CPU核数 / --threads (至少为1)，使总数约等于核数
+    #[arg(long)]
+    pub(crate) ffmpeg_threads: Option<usize>,
+
+    /// 以降低的调度优先级运行 (Unix nice 值，0-19，越大越谦让)，ffmpeg/ffprobe 子进程继承该优先级；不支持的平台上忽略并警告
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(0..=19))]
+    pub(crate) nice: Option<i32>,
+
+    /// 把长视频的时间轴等分为 N 段，每段一个ffmpeg进程并行提取 (受 --threads 线程池限制)；失败的片段按 --retries 单独重试
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(2..),
+        conflicts_with_all = ["every", "positions", "positions_count", "poster_only"]
+    )]
+    pub(crate) segment_parallel: Option<u32>,
+
+    /// 时长 (或 --start/--end 限定的片段) 不短于该秒数的视频才分段
+    #[arg(long, default_value_t = 600.0, requires = "segment_parallel")]
+    pub(crate) segment_min_secs: f64,
+
+    /// 不保留输入目录结构，所有输出目录直接以文件名命名 (同名文件会冲突)
+    #[arg(long)]
+    pub(crate) flatten: bool,
+
+    /// 在输出目录中重建输入目录结构，如 cam1/2024/clip.mp4 输出到 <输出目录>/cam1/2024/clip/ (默认行为)
+    #[arg(long, conflicts_with = "flatten")]
+    preserve_structure: bool,
+
+    /// 多个视频对应同一输出目录时的处理方式：suffix 在目录名后追加源路径的短哈希，
+    /// error 报错退出，skip 只处理其中路径排序最前的一个
+    #[arg(long, value_enum, default_value_t = OnCollision::Suffix)]
+    pub(crate) on_collision: OnCollision,
+
+    /// 抽帧模式：keyframe 提取I帧，scene 按画面变化提取，interval 按固定时间间隔提取
+    #[arg(long, value_enum, default_value_t = ExtractMode::Keyframe)]
+    pub(crate) mode: ExtractMode,
+
+    /// keyframe 模式提取的帧类型 (逗号分隔，如 I,P)，默认只提取I帧
+    #[arg(long, value_enum, value_delimiter = ',', ignore_case = true, conflicts_with = "idr_only")]
+    pub(crate) pict_types: Vec<PictType>,
+
+    /// keyframe 模式只解码关键帧 (输入端 -skip_frame nokey)，不解码其他帧，速度快得多；不含非IDR的I帧
+    #[arg(long)]
+    pub(crate) idr_only: bool,
+
+    /// keyframe 模式先用 ffprobe 读取关键帧时间戳，再逐个在输入端定位提取 (每次调用批量处理多个)，不解码整个视频；读取失败时回退到滤镜方式
+    #[arg(
+        long,
+        conflicts_with_all = ["pict_types", "idr_only", "dedup_filter", "segment_parallel", "positions", "positions_count"]
+    )]
+    pub(crate) fast_seek: bool,
+
+    /// scene 模式的场景变化阈值 (0.0-1.0)，阈值越高提取的帧越少
+    #[arg(long, default_value_t = 0.3, value_parser = parse_scene_threshold)]
+    pub(crate) scene_threshold: f64,
+
+    /// interval 模式的抽帧间隔秒数 (支持小数，如 0.5)
+    #[arg(long, visible_alias = "interval", default_value_t = 30.0, value_parser = parse_interval_secs)]
+    pub(crate) interval_secs: f64,
+
+    /// 在视频时长的指定百分比位置各提取一帧 (逗号分隔，如 5%,25%,50%)
+    #[arg(long, value_delimiter = ',', value_parser = parse_position, conflicts_with_all = ["mode", "positions_count"])]
+    pub(crate) positions: Option<Vec<f64>>,
+
+    /// 每个视频只生成一张海报图 <输出目录>/<相对路径>.<格式>，取 --poster-position 处的关键帧，不创建单独的目录
+    #[arg(long, conflicts_with_all = [
+        "positions", "positions_count", "contact_sheet", "preview", "bookends", "dedup",
+        "min_sharpness", "sharpness_report", "skip_black", "timestamps", "timestamp_names",
+    ])]
+    pub(crate) poster_only: bool,
+
+    /// 海报图在视频时长中的位置 (百分比，默认 20%)，取该位置之后的第一个关键帧
+    #[arg(long, default_value = "20", value_parser = parse_position, requires = "poster_only")]
+    pub(crate) poster_position: f64,
+
+    /// 在视频时长上均匀取 N 个位置各提取一帧 (1-100)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=100), conflicts_with = "mode")]
+    pub(crate) positions_count: Option<u32>,
+
+    /// 只处理从该时间点开始的片段 (HH:MM:SS[.ms] 或秒数)；作为输入选项快速定位
+    #[arg(long, value_parser = parse_time, conflicts_with_all = ["positions", "positions_count"])]
+    pub(crate) start: Option<f64>,
+
+    /// 只处理到该时间点为止 (HH:MM:SS[.ms] 或秒数，相对于视频开头)
+    #[arg(long, value_parser = parse_time, conflicts_with_all = ["positions", "positions_count"])]
+    pub(crate) end: Option<f64>,
+
+    /// 从 --start (默认开头) 起只处理这么长的片段 (HH:MM:SS[.ms] 或秒数)，与 --end 二选一
+    #[arg(
+        long,
+        visible_alias = "length",
+        value_parser = parse_time,
+        conflicts_with_all = ["end", "positions", "positions_count"]
+    )]
+    duration: Option<f64>,
+
+    /// 每个视频最多输出的帧数 (作为输出选项 -frames:v，得到的是滤镜选中的前 N 帧)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["positions", "positions_count"])]
+    pub(crate) max_frames: Option<u64>,
+
+    /// 只保留每 K 个选中帧中的第一个，在选帧之后、缩放之前均匀抽稀 (如 keyframe 模式下每 K 个I帧取一帧)
+    #[arg(
+        long,
+        visible_alias = "sample-rate",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["positions", "positions_count"]
+    )]
+    pub(crate) every: Option<u64>,
+
+    /// 输出帧最大宽度 (保持宽高比，不放大)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub(crate) max_width: Option<u32>,
+
+    /// 输出帧最大高度 (保持宽高比，不放大)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub(crate) max_height: Option<u32>,
+
+    /// 以帧在源视频中的时间戳命名输出文件 (如 keyframe_000123.45s.jpg)
+    #[arg(long, conflicts_with_all = ["positions", "positions_count"])]
+    pub(crate) timestamp_names: bool,
+
+    /// 输出帧文件名模板，可用 {stem} (源文件名)、{index} 或 {index:05} (帧序号，可补零)、{ext}；必须包含帧序号，缺少 {ext} 时自动追加 .{ext}
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = parse_frame_pattern,
+        conflicts_with_all = ["positions", "positions_count", "timestamp_names", "poster_only"]
+    )]
+    pub(crate) pattern: Option<FramePattern>,
+
+    /// 提取后按感知哈希去除与上一保留帧几乎相同的帧；被删除的帧留下编号空缺，保留帧的文件名不变，清单中记录每个被删除帧并入的保留帧
+    #[arg(long)]
+    pub(crate) dedup: bool,
+
+    /// 去重阈值：与上一保留帧的哈希汉明距离 (0-64) 小于该值即视为重复
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..=64))]
+    pub(crate) dedup_threshold: u32,
+
+    /// 在FFmpeg滤镜链中加入 mpdecimate 丢弃与上一输出帧几乎相同的帧 (比 --dedup 快，但阈值不可调，也不记录并入关系)
+    #[arg(long, conflicts_with_all = ["dedup", "positions", "positions_count"])]
+    pub(crate) dedup_filter: bool,
+
+    /// 删除清晰度 (拉普拉斯方差) 低于该值的模糊帧，如镜头平移中的帧；删除数记录在清单中
+    #[arg(long, value_parser = parse_sharpness)]
+    pub(crate) min_sharpness: Option<f64>,
+
+    /// 只在每个输出目录写入 sharpness.csv 记录每帧清晰度，不删除任何帧，用于确定 --min-sharpness 的取值
+    #[arg(long, conflicts_with = "min_sharpness")]
+    pub(crate) sharpness_report: bool,
+
+    /// 删除黑屏或接近全黑的帧 (如片头黑场)；被删除的帧留下编号空缺，删除数记录在清单和汇总中
+    #[arg(long)]
+    pub(crate) skip_black: bool,
+
+    /// 黑色像素的亮度上限 (0-255)
+    #[arg(long, default_value_t = 10, requires = "skip_black")]
+    pub(crate) black_threshold: u8,
+
+    /// 黑色像素占比达到该值 (0.0-1.0) 即视为黑帧
+    #[arg(long, default_value_t = 0.98, requires = "skip_black", value_parser = parse_ratio)]
+    pub(crate) black_ratio: f64,
+
+    /// 在每个输出目录生成 sheet.jpg 缩略图总览，按 COLS 列 (默认4) 排列关键帧并标注时间；COLSxROWS 时帧数超过格数则均匀抽取 (含首尾两帧)
+    #[arg(
+        long,
+        value_name = "COLS[xROWS]",
+        num_args = 0..=1,
+        default_missing_value = "4",
+        value_parser = parse_sheet_layout
+    )]
+    pub(crate) contact_sheet: Option<SheetLayout>,
+
+    /// 生成缩略图总览后删除单独的关键帧文件，只保留总览
+    #[arg(long, requires = "contact_sheet")]
+    pub(crate) sheet_only: bool,
+
+    /// 缩略图总览中每格的宽度 (像素)，高度按第一帧的宽高比计算
+    #[arg(long, default_value_t = 320, value_parser = clap::value_parser!(u32).range(16..=4096))]
+    pub(crate) sheet_tile_width: u32,
+
+    /// 缩略图总览最多包含的帧数 (默认全部)
+    #[arg(long, requires = "contact_sheet")]
+    pub(crate) sheet_max_frames: Option<usize>,
+
+    /// 另外保存第一帧和最后一帧为 first.<格式> 和 last.<格式> (不论是否为关键帧，指定 --start/--end 时取该范围的首尾)
+    #[arg(long)]
+    pub(crate) bookends: bool,
+
+    /// 额外生成循环播放的动画预览 preview.gif / preview.webp (由选中的帧组成，最长10秒)；预览失败只警告，不影响关键帧
+    #[arg(long, value_enum)]
+    pub(crate) preview: Option<PreviewFormat>,
+
+    /// 动画预览的宽度 (像素)
+    #[arg(
+        long,
+        default_value_t = 320,
+        requires = "preview",
+        value_parser = clap::value_parser!(u32).range(16..=4096)
+    )]
+    pub(crate) preview_width: u32,
+
+    /// 动画预览的帧率 (每秒显示的选中帧数)
+    #[arg(
+        long,
+        default_value_t = 2,
+        requires = "preview",
+        value_parser = clap::value_parser!(u32).range(1..=30)
+    )]
+    pub(crate) preview_fps: u32,
+
+    /// 在每个输出目录写入 timestamps.csv，记录每个输出帧文件对应的时间 (秒)
+    #[arg(long)]
+    pub(crate) timestamps: bool,
+
+    /// 同时限定最大宽高，如 1280x720 (等同于 --max-width 1280 --max-height 720)
+    #[arg(long, value_parser = parse_scale, conflicts_with_all = ["max_width", "max_height"])]
+    pub(crate) scale: Option<(u32, u32)>,
+
+    /// 关键帧质量 (1-31, 1为最佳)；可按源视频扩展名覆盖，如 2,mov=1,avi=5 (未列出的扩展名使用全局值，省略时为2)
+    #[arg(short, long, default_value = "2", value_parser = parse_quality)]
+    pub(crate) quality: Quality,
+
+    /// 关键帧输出格式 (质量参数会按格式映射到对应编码器)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Jpg)]
+    pub(crate) format: OutputFormat,
+
+    /// 把每个视频的关键帧和清单打包为一个归档文件 (<输出目录>.zip 或 .tar)，不保留散落的帧文件；跳过判断改为检查归档是否存在
+    #[arg(long, value_enum, conflicts_with = "poster_only")]
+    pub(crate) archive: Option<ArchiveFormat>,
+
+    /// zip 归档的压缩方式：stored 不压缩 (默认，jpg 等已压缩的帧再压缩收益很小)，deflate 为标准压缩
+    #[arg(long, value_enum, requires = "archive")]
+    pub(crate) archive_compression: Option<ArchiveCompression>,
+
+    /// 文件扩展名过滤 (逗号分隔，不区分大小写，可带开头的点如 .mp4)
+    #[arg(long, default_value = "mp4,mov,avi,mkv,flv")]
+    pub(crate) extensions: String,
+
+    /// 硬件解码方式 (对应FFmpeg的 -hwaccel)，none 表示不传该参数、只用软件解码；启用时若FFmpeg失败，会自动以软件解码重试一次
+    #[arg(long, value_enum, default_value_t = HwAccel::Auto)]
+    pub(crate) hwaccel: HwAccel,
+
+    /// 不按视频的旋转元数据转正画面，按编码时的原始方向输出帧
+    #[arg(long)]
+    pub(crate) no_autorotate: bool,
+
+    /// HDR (HDR10/HLG) 源转为 SDR 时的色调映射算法，auto 为 hable；SDR 源不受影响。需要FFmpeg支持 zscale 滤镜，不为 off 时启动时检查
+    #[arg(long, value_enum, default_value_t = Tonemap::Auto)]
+    pub(crate) tonemap: Tonemap,
+
+    /// FFmpeg可执行文件路径
+    #[arg(long, env = "FFMPEG_PATH", default_value = "ffmpeg")]
+    pub(crate) ffmpeg_path: PathBuf,
+
+    /// ffprobe可执行文件路径 (默认与FFmpeg位于同一目录)
+    #[arg(long, env = "FFPROBE_PATH")]
+    pub(crate) ffprobe_path: Option<PathBuf>,
+
+    /// FFmpeg启动失败或返回非零状态时的额外重试次数，每次重试前等待时间递增
+    #[arg(long, default_value_t = 0)]
+    pub(crate) retries: u32,
+
+    /// 单个视频的超时时间 (秒)，包括该视频的所有FFmpeg调用和重试，超时后终止FFmpeg并删除不完整的输出
+    #[arg(long, visible_alias = "timeout-secs")]
+    pub(crate) timeout: Option<u64>,
+
+    /// 只处理时长不短于该值的视频 (秒数或 HH:MM:SS)，由 ffprobe 获取时长
+    #[arg(long, value_parser = parse_time)]
+    pub(crate) min_duration: Option<f64>,
+
+    /// 只处理时长不超过该值的视频 (秒数或 HH:MM:SS)
+    #[arg(long, value_parser = parse_time)]
+    pub(crate) max_duration: Option<f64>,
+
+    /// 只处理画面高度不低于该值的视频 (像素，如 720)
+    #[arg(long)]
+    pub(crate) min_height: Option<u32>,
+
+    /// 只处理不小于该大小的文件 (字节，可带 K/M/G 后缀，如 10M)
+    #[arg(long, value_parser = parse_size)]
+    pub(crate) min_size: Option<u64>,
+
+    /// 只处理不大于该大小的文件 (字节，可带 K/M/G 后缀，如 4G)
+    #[arg(long, value_parser = parse_size)]
+    pub(crate) max_size: Option<u64>,
+
+    /// 输出总量上限 (可带 K/M/G/T 后缀)：开始前输出所在磁盘可用空间不足该值时中止，处理中新写入的输出超过该值后停止开始新的视频
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub(crate) max_output_size: Option<u64>,
+
+    /// ffprobe 无法确定时长或高度时，是否仍处理该文件 (仅在指定了对应筛选条件时生效)
+    #[arg(long, value_enum, default_value_t = OnUnknown::Exclude)]
+    pub(crate) on_unknown: OnUnknown,
+
+    /// 跳过最近 N 秒内修改过的文件 (可能仍在录制或复制中)；监视模式请用 --settle-secs
+    #[arg(long, value_name = "SECS", conflicts_with = "watch")]
+    pub(crate) min_age: Option<u64>,
+
+    /// 最大递归深度：输入目录本身为0，1表示只处理输入目录下直接包含的文件 (默认不限)
+    #[arg(long)]
+    pub(crate) max_depth: Option<usize>,
+
+    /// 扫描时进入符号链接指向的目录 (检测到链接循环时警告并跳过，同一文件只处理一次)
+    #[arg(long)]
+    pub(crate) follow_symlinks: bool,
+
+    /// 跳过以 . 开头的隐藏文件和目录 (如 .snapshots)
+    #[arg(long)]
+    pub(crate) skip_hidden: bool,
+
+    /// 只处理匹配的文件 (glob，匹配相对于输入目录的路径或文件名，可重复指定，如 "CAM?_*.mp4")
+    #[arg(long)]
+    pub(crate) include: Vec<String>,
+
+    /// 排除匹配的文件或目录 (glob，相对于输入目录，可重复指定，如 "**/trash/**")，匹配的目录不再进入
+    #[arg(long)]
+    pub(crate) exclude: Vec<String>,
+
+    /// 处理顺序：name 按路径，size 从小到大，mtime 从旧到新 (相同时按路径)；--limit 按此顺序截取
+    #[arg(long, value_enum, default_value_t = SortKey::Name)]
+    pub(crate) sort: SortKey,
+
+    /// 调度顺序：size/duration 从大到小先开始处理，避免最后只剩一个大文件独占一个线程；name 按路径，none 保持 --sort 的顺序
+    #[arg(long, value_enum, default_value_t = Schedule::Size)]
+    pub(crate) schedule: Schedule,
+
+    /// 只处理前 N 个视频：在 --include/--exclude、预检和时长筛选等全部筛选之后作用于最终列表，适合试验新参数
+    #[arg(long, conflicts_with = "watch")]
+    pub(crate) limit: Option<usize>,
+
+    /// 只列出扫描选中的视频文件后退出，用于检查 --include/--exclude 等筛选条件
+    #[arg(long, conflicts_with_all = ["dry_run", "watch"])]
+    pub(crate) list_only: bool,
+
+    /// 只用 ffprobe 列出每个视频的编码、分辨率、时长、帧率和关键帧数后退出，不提取；指定 --manifest 时写为 JSON
+    #[arg(long, conflicts_with_all = ["dry_run", "watch", "list_only"])]
+    pub(crate) probe_only: bool,
+
+    /// 输出更详细的日志：-v 显示发现/跳过/排除的路径和执行的FFmpeg命令，-vv 显示全部调试信息
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub(crate) verbose: u8,
+
+    /// 只输出错误，不显示进度条和提取结果汇总 (--progress-format json 的事件照常输出)；-q 已用于 --quality，因此只有长选项
+    #[arg(long)]
+    pub(crate) quiet: bool,
+
+    /// 同时把日志追加写入该文件 (不带颜色，含时间；至少为调试级别，包括每个视频执行的FFmpeg命令)
+    #[arg(long)]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// 额外的FFmpeg输入参数，插入到 -i 之前 (按shell规则拆分，如 "-skip_frame nokey")
+    #[arg(long, value_parser = parse_shell_args, allow_hyphen_values = true)]
+    pub(crate) ffmpeg_input_args: Option<ShellArgs>,
+
+    /// 额外的FFmpeg输出参数，插入到输出文件名之前 (按shell规则拆分，如 "-map 0:v:0")
+    #[arg(long, value_parser = parse_shell_args, allow_hyphen_values = true)]
+    pub(crate) ffmpeg_output_args: Option<ShellArgs>,
+
+    /// 仅列出计划处理的视频及输出目录，不调用FFmpeg；没有需要处理的视频时以退出码 3 结束
+    #[arg(long)]
+    pub(crate) dry_run: bool,
+
+    /// 试运行时用 ffprobe 估计每个视频将提取的帧数 (关键帧模式需统计关键帧，较慢)
+    #[arg(long, requires = "dry_run")]
+    pub(crate) estimate: bool,
+
+    /// 不显示进度条，改为每完成一个视频打印一行 "X/Y" 进度 (适合CI或重定向到文件)
+    #[arg(long)]
+    pub(crate) no_progress: bool,
+
+    /// 进度和结果的输出格式：human 为进度条和提取结果汇总，json 在标准输出逐行输出 JSON 事件 (NDJSON)，
+    /// 依次为 discovered、每个视频的 started 与 finished/failed、最后的 summary；其他提示信息仍输出到标准错误
+    #[arg(long, value_enum, default_value_t = ProgressFormat::Human)]
+    pub(crate) progress_format: ProgressFormat,
+
+    /// 同 --progress-format json
+    #[arg(long)]
+    json: bool,
+
+    /// 单个视频失败时继续处理其余文件，结束时汇总失败列表
+    #[arg(long)]
+    pub(crate) keep_going: bool,
+
+    /// 提取成功且写出至少一帧后对源视频的处理：keep 保留，move:<目录> 移动到该目录 (保留相对输入目录的结构，重名时追加序号)，
+    /// delete 删除 (需同时指定 --yes-really-delete)；处理结果记入 --report
+    #[arg(long, value_name = "ACTION", value_parser = parse_on_success, default_value = "keep")]
+    pub(crate) on_success: OnSuccess,
+
+    /// 确认 --on-success delete 删除源视频，删除后无法恢复
+    #[arg(long)]
+    yes_really_delete: bool,
+
+    /// FFmpeg日志目录：失败视频的完整FFmpeg输出保存为 <目录>/<相对输出路径>.log (默认保存为输出目录下的 ffmpeg.log)
+    #[arg(long)]
+    pub(crate) log_dir: Option<PathBuf>,
+
+    /// 成功的视频也保留FFmpeg日志 (默认只保留失败视频的)
+    #[arg(long)]
+    pub(crate) keep_logs: bool,
+
+    /// 处理完现有文件后继续监视输入目录，处理新出现的视频，按 Ctrl-C 结束并打印汇总
+    #[arg(long, conflicts_with_all = ["dry_run", "input_list"])]
+    pub(crate) watch: bool,
+
+    /// 监视模式下文件大小保持不变多少秒后才视为写入完成
+    #[arg(long, default_value_t = 5)]
+    pub(crate) settle_secs: u64,
+
+    /// 输出目录已存在时清除其中已提取的关键帧并重新提取
+    #[arg(long, conflicts_with = "force")]
+    pub(crate) overwrite: bool,
+
+    /// 输出目录已存在时整个删除并重新提取
+    #[arg(long)]
+    pub(crate) force: bool,
+
+    /// 汇总清单文件路径 (默认为输出目录下的 run-manifest.json)；--probe-only 时为探测结果的输出路径
+    #[arg(long)]
+    pub(crate) manifest: Option<PathBuf>,
+
+    /// 跳过处理前的 ffprobe 校验 (默认会剔除无法读取或没有视频流的文件)
+    #[arg(long)]
+    pub(crate) no_validate: bool,
+
+    /// 运行结束后写入报告，格式由扩展名决定 (.json 或 .csv)
+    #[arg(long)]
+    pub(crate) report: Option<PathBuf>,
+
+    /// 状态文件路径，记录已处理视频的大小和修改时间；
+    /// 指定后仅当源文件与记录一致时才跳过，源文件变化则重新提取 (不再以输出目录是否存在判断)
+    #[arg(long)]
+    pub(crate) state_file: Option<PathBuf>,
+
+    /// 忽略状态文件中的已有记录，重新处理所有视频并重建状态文件
+    #[arg(long, requires = "state_file")]
+    pub(crate) rescan: bool,
+
+    /// 不写入汇总清单及每个视频目录下的 manifest.json
+    #[arg(long, conflicts_with = "manifest")]
+    pub(crate) no_manifest: bool,
+}
+
+// 抽帧模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExtractMode {
+    Keyframe,
+    Scene,
+    Interval,
+}
+
+// keyframe 模式可选的帧类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PictType {
+    #[value(name = "I")]
+    I,
+    #[value(name = "P")]
+    P,
+    #[value(name = "B")]
+    B,
+}
+
+impl PictType {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            PictType::I => "I",
+            PictType::P => "P",
+            PictType::B => "B",
+        }
+    }
+}
+
+fn parse_scene_threshold(value: &str) -> Result<f64, String> {
+    let threshold: f64 = value.parse().map_err(|_| format!("无效的阈值: {}", value))?;
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(format!("阈值必须在 0.0 到 1.0 之间: {}", value));
+    }
+    Ok(threshold)
+}
+
+fn parse_sharpness(value: &str) -> Result<f64, String> {
+    let sharpness: f64 = value.parse().map_err(|_| format!("无效的清晰度阈值: {}", value))?;
+    if !sharpness.is_finite() || sharpness < 0.0 {
+        return Err("清晰度阈值不能为负数".to_string());
+    }
+    Ok(sharpness)
+}
+
+// 解析 --quality：逗号分隔，至多一个不带扩展名的全局值，其余为 扩展名=质量，扩展名不区分大小写
+fn parse_quality(value: &str) -> Result<Quality, String> {
+    let parse_value = |text: &str| {
+        text.trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|q| (1..=31).contains(q))
+            .ok_or_else(|| format!("质量必须为 1-31 的整数: {}", text.trim()))
+    };
+    let mut default = None;
+    let mut by_extension = BTreeMap::new();
+    for entry in value.split(',').map(str::trim) {
+        if entry.is_empty() {
+            return Err(format!("质量列表中有空项: {}", value));
+        }
+        match entry.split_once('=') {
+            Some((ext, quality)) => {
+                let ext = ext.trim().trim_start_matches('.').to_lowercase();
+                if ext.is_empty() {
+                    return Err(format!("缺少扩展名: {}", entry));
+                }
+                if by_extension.insert(ext, parse_value(quality)?).is_some() {
+                    return Err(format!("扩展名重复: {}", entry));
+                }
+            }
+            None if default.is_some() => return Err(format!("只能指定一个全局质量值: {}", value)),
+            None => default = Some(parse_value(entry)?),
+        }
+    }
+    Ok(Quality { default: default.unwrap_or(2), by_extension })
+}
+
+fn parse_on_success(value: &str) -> Result<OnSuccess, String> {
+    match value {
+        "keep" => Ok(OnSuccess::Keep),
+        "delete" => Ok(OnSuccess::Delete),
+        _ => match value.strip_prefix("move:") {
+            Some(dir) if !dir.is_empty() => Ok(OnSuccess::Move(PathBuf::from(dir))),
+            _ => Err(format!("无效的处理方式: {} (可选 keep、move:<目录>、delete)", value)),
+        },
+    }
+}
+
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    let ratio: f64 = value.parse().map_err(|_| format!("无效的比例: {}", value))?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(format!("比例必须在 0.0 到 1.0 之间: {}", value));
+    }
+    Ok(ratio)
+}
+
+fn parse_interval_secs(value: &str) -> Result<f64, String> {
+    let secs: f64 = value.parse().map_err(|_| format!("无效的间隔: {}", value))?;
+    if !secs.is_finite() || secs <= 0.0 {
+        return Err(format!("间隔必须为正数: {}", value));
+    }
+    Ok(secs)
+}
+
+// 解析百分比位置，如 "25%" 或 "25"
+fn parse_position(value: &str) -> Result<f64, String> {
+    let number = value.trim().trim_end_matches('%');
+    let percent: f64 = number.parse().map_err(|_| format!("无效的位置: {}", value))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("位置必须在 0% 到 100% 之间: {}", value));
+    }
+    Ok(percent)
+}
+
+// 解析 WxH 形式的尺寸
+fn parse_scale(value: &str) -> Result<(u32, u32), String> {
+    let (w, h) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("尺寸格式应为 WxH: {}", value))?;
+    let parse = |n: &str| match n.trim().parse::<u32>() {
+        Ok(n) if n >= 2 => Ok(n),
+        _ => Err(format!("无效的尺寸: {}", value)),
+    };
+    Ok((parse(w)?, parse(h)?))
+}
+
+// 缩略图总览的网格：列数，以及可选的行数 (不指定时行数随帧数增加)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SheetLayout {
+    pub(crate) cols: u32,
+    pub(crate) rows: Option<u32>,
+}
+
+fn parse_sheet_layout(value: &str) -> Result<SheetLayout, String> {
+    let parse = |n: &str| match n.trim().parse::<u32>() {
+        Ok(n) if (1..=64).contains(&n) => Ok(n),
+        _ => Err(format!("无效的网格: {} (应为 COLS 或 COLSxROWS，各为1-64)", value)),
+    };
+    match value.split_once(['x', 'X']) {
+        Some((cols, rows)) => Ok(SheetLayout {
+            cols: parse(cols)?,
+            rows: Some(parse(rows)?),
+        }),
+        None => Ok(SheetLayout {
+            cols: parse(value)?,
+            rows: None,
+        }),
+    }
+}
+
+// 解析时间点：秒数 (如 90.5) 或 [HH:]MM:SS[.ms]
+fn parse_time(value: &str) -> Result<f64, String> {
+    let invalid = || format!("无效的时间: {} (应为秒数或 HH:MM:SS)", value);
+    let mut seconds = 0.0;
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    for (i, part) in parts.iter().enumerate() {
+        let number: f64 = part.parse().map_err(|_| invalid())?;
+        let is_last = i == parts.len() - 1;
+        if !number.is_finite() || number < 0.0 || (!is_last && number.fract() != 0.0) {
+            return Err(invalid());
+        }
+        if i > 0 && number >= 60.0 {
+            return Err(invalid());
+        }
+        seconds = seconds * 60.0 + number;
+    }
+    Ok(seconds)
+}
+
+// 解析文件大小：字节数，或带 K/M/G/T 后缀 (1024 进制，可带小数，如 1.5G)
+fn parse_size(value: &str) -> Result<u64, String> {
+    let invalid = || format!("无效的大小: {} (应为字节数或带 K/M/G 后缀，如 10M)", value);
+    let text = value.trim().to_ascii_uppercase();
+    let text = text.strip_suffix('B').unwrap_or(&text);
+    let (number, unit) = match text.chars().last() {
+        Some('K') => (&text[..text.len() - 1], 1u64 << 10),
+        Some('M') => (&text[..text.len() - 1], 1 << 20),
+        Some('G') => (&text[..text.len() - 1], 1 << 30),
+        Some('T') => (&text[..text.len() - 1], 1 << 40),
+        _ => (text, 1),
+    };
+    let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(invalid());
+    }
+    Ok((number * unit as f64) as u64)
+}
+
+// 以 1024 进制格式化字节数，如 1.5G
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = None;
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = Some(next);
+    }
+    match unit {
+        Some(unit) => format!("{:.1}{}", size, unit),
+        None => format!("{}B", bytes),
+    }
+}
+
+// --pattern 文件名模板，及识别、统计和清除按模板输出的关键帧时使用的匹配规则
+#[derive(Debug, Clone)]
+pub(crate) struct FramePattern {
+    parts: Vec<PatternPart>,
+    matcher: globset::GlobMatcher,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PatternPart {
+    Literal(String),
+    Stem,
+    Ext,
+    // 补零宽度，0 表示不补零
+    Index(usize),
+}
+
+fn parse_frame_pattern(value: &str) -> Result<FramePattern, String> {
+    let mut parts = Vec::new();
+    let mut rest = value;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(PatternPart::Literal(rest[..open].to_string()));
+        }
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("模板中的 {{ 没有对应的 }}: {}", value))?;
+        let name = &rest[open + 1..open + close];
+        parts.push(match name {
+            "stem" => PatternPart::Stem,
+            "ext" => PatternPart::Ext,
+            "index" => PatternPart::Index(0),
+            _ => match name.strip_prefix("index:") {
+                Some(width) => match width.parse::<usize>() {
+                    Ok(width @ 1..=20) => PatternPart::Index(width),
+                    _ => return Err(format!("无效的补零宽度: {{{}}} (应为1-20)", name)),
+                },
+                None => return Err(format!("未知的占位符: {{{}}}", name)),
+            },
+        });
+        rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(PatternPart::Literal(rest.to_string()));
+    }
+    let literal = |part: &PatternPart| match part {
+        PatternPart::Literal(text) => Some(text.clone()),
+        _ => None,
+    };
+    if parts.iter().filter_map(literal).any(|text| text.contains(['/', '\\', '}'])) {
+        return Err("模板只能是文件名，不能包含路径分隔符或多余的 }".to_string());
+    }
+    // 没有帧序号时所有帧会写入同一个文件
+    match parts.iter().filter(|part| matches!(part, PatternPart::Index(_))).count() {
+        1 => {}
+        0 => return Err("模板必须包含帧序号 {index} 或 {index:05}".to_string()),
+        _ => return Err("模板只能包含一个帧序号".to_string()),
+    }
+    if !parts.contains(&PatternPart::Ext) {
+        parts.push(PatternPart::Literal(".".to_string()));
+        parts.push(PatternPart::Ext);
+    }
+    let matcher = pattern_matcher(&parts).map_err(|e| format!("{:#}", e))?;
+    Ok(FramePattern { parts, matcher })
+}
+
+// 识别按模板输出的文件：源文件名匹配任意字符，扩展名为任一输出格式
+fn pattern_matcher(parts: &[PatternPart]) -> Result<globset::GlobMatcher> {
+    let extensions: Vec<&str> = OutputFormat::value_variants()
+        .iter()
+        .map(|format| format.extension())
+        .collect();
+    let glob: String = parts
+        .iter()
+        .map(|part| match part {
+            PatternPart::Literal(text) => text
+                .chars()
+                .map(|c| match c {
+                    '*' | '?' | '[' | ']' | '{' | '}' | ',' => format!("[{}]", c),
+                    c => c.to_string(),
+                })
+                .collect(),
+            PatternPart::Stem => "*".to_string(),
+            PatternPart::Ext => format!("{{{}}}", extensions.join(",")),
+            PatternPart::Index(_) => "[0-9]*".to_string(),
+        })
+        .collect();
+    Ok(globset::Glob::new(&glob)
+        .with_context(|| format!("无法匹配文件名模板: {}", glob))?
+        .compile_matcher())
+}
+
+impl FramePattern {
+    // FFmpeg image2 输出模板：帧序号译为 %0Nd，其余部分的 % 转义为 %%
+    pub(crate) fn ffmpeg_pattern(&self, stem: &str, ext: &str) -> String {
+        let escape = |text: &str| text.replace('%', "%%");
+        self.parts
+            .iter()
+            .map(|part| match part {
+                PatternPart::Literal(text) => escape(text),
+                PatternPart::Stem => escape(stem),
+                PatternPart::Ext => ext.to_string(),
+                PatternPart::Index(0) => "%d".to_string(),
+                PatternPart::Index(width) => format!("%0{}d", width),
+            })
+            .collect()
+    }
+
+    // 第 index 帧的文件名
+    pub(crate) fn file_name(&self, stem: &str, ext: &str, index: usize) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                PatternPart::Literal(text) => text.clone(),
+                PatternPart::Stem => stem.to_string(),
+                PatternPart::Ext => ext.to_string(),
+                PatternPart::Index(width) => format!("{:0width$}", index, width = *width),
+            })
+            .collect()
+    }
+
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        self.matcher.is_match(name)
+    }
+}
+
+// 按shell规则拆分后的一组命令行参数
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ShellArgs(pub(crate) Vec<String>);
+
+fn parse_shell_args(value: &str) -> Result<ShellArgs, String> {
+    shell_words::split(value)
+        .map(ShellArgs)
+        .map_err(|e| format!("无法解析参数 {:?}: {}", value, e))
+}
+
+// --archive 的归档格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ArchiveFormat {
+    Zip,
+    Tar,
+}
+
+impl ArchiveFormat {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+        }
+    }
+}
+
+// zip 归档中文件的压缩方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ArchiveCompression {
+    Stored,
+    Deflate,
+}
+
+// 关键帧输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    Jpg,
+    Png,
+    Webp,
+    Avif,
+}
+
+impl OutputFormat {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Jpg => "jpg",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Avif => "avif",
+        }
+    }
+
+    // 对应的FFmpeg编码器名称
+    pub(crate) fn encoder(self) -> &'static str {
+        match self {
+            OutputFormat::Jpg => "mjpeg",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "libwebp",
+            OutputFormat::Avif => "libaom-av1",
+        }
+    }
+
+    // 将 1-31 的质量值 (1为最佳) 映射为各编码器自己的参数
+    pub(crate) fn quality_args(self, quality: u8) -> Vec<String> {
+        let step = u32::from(quality.clamp(1, 31)) - 1;
+        match self {
+            OutputFormat::Jpg => vec!["-q:v".into(), quality.to_string()],
+            // PNG为无损格式，质量值越好压缩级别越高 (9..0)
+            OutputFormat::Png => vec!["-compression_level".into(), (9 - step * 9 / 30).to_string()],
+            // libwebp 质量为 0-100，100为最佳
+            OutputFormat::Webp => vec!["-quality".into(), (100 - step * 100 / 30).to_string()],
+            // libaom 静态图片使用 CRF 0-63，0为最佳
+            OutputFormat::Avif => vec![
+                "-still-picture".into(),
+                "1".into(),
+                "-crf".into(),
+                (step * 63 / 30).to_string(),
+            ],
+        }
+    }
+}
+
+// HDR 源的色调映射方式，auto 等同于 hable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Tonemap {
+    Auto,
+    Off,
+    Hable,
+    Reinhard,
+}
+
+impl Tonemap {
+    // 使用的色调映射算法；SDR 源或 off 时为 None
+    pub(crate) fn algorithm(self, hdr: bool) -> Option<&'static str> {
+        match (self, hdr) {
+            (_, false) | (Tonemap::Off, _) => None,
+            (Tonemap::Auto | Tonemap::Hable, true) => Some("hable"),
+            (Tonemap::Reinhard, true) => Some("reinhard"),
+        }
+    }
+}
+
+// 硬件解码方式，None 表示不传 -hwaccel 参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum HwAccel {
+    Auto,
+    None,
+    Cuda,
+    Vaapi,
+    Qsv,
+    Videotoolbox,
+}
+
+impl HwAccel {
+    pub(crate) fn args(self) -> Vec<&'static str> {
+        let name = match self {
+            HwAccel::Auto => "auto",
+            HwAccel::None => return Vec::new(),
+            HwAccel::Cuda => "cuda",
+            HwAccel::Vaapi => "vaapi",
+            HwAccel::Qsv => "qsv",
+            HwAccel::Videotoolbox => "videotoolbox",
+        };
+        vec!["-hwaccel", name]
+    }
+}
+
+// 动画预览格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PreviewFormat {
+    Gif,
+    Webp,
+}
+
+impl PreviewFormat {
+    pub(crate) fn file_name(self) -> &'static str {
+        match self {
+            PreviewFormat::Gif => "preview.gif",
+            PreviewFormat::Webp => "preview.webp",
+        }
+    }
+}
+
+// 发现的视频的排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SortKey {
+    Name,
+    Size,
+    Mtime,
+}
+
+// 交给线程池的调度顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Schedule {
+    Size,
+    Duration,
+    Name,
+    None,
+}
+
+// 筛选条件所需的元数据无法获取时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OnUnknown {
+    Include,
+    Exclude,
+}
+
+// 进度和结果的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ProgressFormat {
+    Human,
+    Json,
+}
+
+// --quality 的全局质量值及按源视频扩展名的覆盖
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Quality {
+    default: u8,
+    by_extension: BTreeMap<String, u8>,
+}
+
+impl Quality {
+    pub(crate) fn for_video(&self, video_path: &Path) -> u8 {
+        video_path
+            .extension()
+            .and_then(|ext| self.by_extension.get(&ext.to_string_lossy().to_lowercase()))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+// 提取成功后对源视频的处理 (--on-success)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OnSuccess {
+    Keep,
+    Move(PathBuf),
+    Delete,
+}
+
+// 输出目录冲突时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OnCollision {
+    Suffix,
+    Error,
+    Skip,
+}
+
+// 换算和校验命令行与库接口共用的参数：--threads 0 和 --duration 的换算，以及选项组合的检查
+pub(crate) fn prepare_args(args: &mut Args) -> Result<()> {
+    // --threads 0 表示全部逻辑核心；在计算每个ffmpeg的线程数和建线程池之前换算
+    if args.threads == 0 {
+        args.threads = num_cpus::get();
+    }
+    if args.json {
+        args.progress_format = ProgressFormat::Json;
+    }
+    if args.archive == Some(ArchiveFormat::Tar) && args.archive_compression.is_some() {
+        anyhow::bail!("--archive-compression 只用于 zip 归档");
+    }
+    if args.on_success == OnSuccess::Delete && !args.yes_really_delete {
+        anyhow::bail!("--on-success delete 会删除源视频且无法恢复，确认请同时指定 --yes-really-delete");
+    }
+    let json = args.progress_format == ProgressFormat::Json;
+    if json && (args.dry_run || args.list_only || args.probe_only) {
+        anyhow::bail!("--progress-format json 不能与 --dry-run、--list-only 或 --probe-only 同时使用");
+    }
+    // --duration 换算为 --end，之后所有调用只需处理 --start/--end
+    if let Some(duration) = args.duration {
+        if duration <= 0.0 {
+            anyhow::bail!("--duration 必须大于0");
+        }
+        args.end = Some(args.start.unwrap_or(0.0) + duration);
+    }
+    if let (Some(start), Some(end)) = (args.start, args.end) {
+        if end <= start {
+            anyhow::bail!("--end ({}) 必须晚于 --start ({})", end, start);
+        }
+    }
+    if args.input.len() > 1 && args.input.iter().any(|input| input.as_os_str() == "-") {
+        anyhow::bail!("--input - (从标准输入读取列表) 不能与其他输入目录同时使用");
+    }
+    if (!args.pict_types.is_empty() || args.idr_only) && args.mode != ExtractMode::Keyframe {
+        anyhow::bail!("--pict-types 和 --idr-only 只用于 keyframe 模式");
+    }
+    if args.fast_seek && args.mode != ExtractMode::Keyframe {
+        anyhow::bail!("--fast-seek 只用于 keyframe 模式");
+    }
+    if get_video_extensions(&args.extensions).is_empty() {
+        anyhow::bail!("--extensions 至少需要一个扩展名");
+    }
+    if args.dedup && args.format == OutputFormat::Avif {
+        anyhow::bail!("--dedup 不支持 avif 格式，请改用 jpg、png 或 webp");
+    }
+    let scores_sharpness = args.min_sharpness.is_some() || args.sharpness_report;
+    if scores_sharpness && args.format == OutputFormat::Avif {
+        anyhow::bail!(
+            "--min-sharpness 和 --sharpness-report 不支持 avif 格式，请改用 jpg、png 或 webp"
+        );
+    }
+    if args.skip_black && args.format == OutputFormat::Avif {
+        anyhow::bail!("--skip-black 不支持 avif 格式，请改用 jpg、png 或 webp");
+    }
+    if args.contact_sheet.is_some() && args.format == OutputFormat::Avif {
+        anyhow::bail!("--contact-sheet 不支持 avif 格式，请改用 jpg、png 或 webp");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_with_extension_overrides() {
+        let quality = parse_quality("2,mov=1,avi=5").unwrap();
+        assert_eq!(quality.default, 2);
+        assert_eq!(
+            quality.by_extension,
+            BTreeMap::from([("avi".to_string(), 5), ("mov".to_string(), 1)])
+        );
+        assert_eq!(quality.for_video(Path::new("/videos/a.MOV")), 1);
+        assert_eq!(quality.for_video(Path::new("/videos/b.avi")), 5);
+        assert_eq!(quality.for_video(Path::new("/videos/c.mp4")), 2);
+        assert_eq!(quality.for_video(Path::new("/videos/no_extension")), 2);
+        assert_eq!(parse_quality("mov=1").unwrap().default, 2);
+    }
+
+    #[test]
+    fn malformed_quality_is_rejected() {
+        for value in ["2,,mov=1", "=3", "mov=1,MOV=2", "2,3", "mov=high", "high", "0", "32", "mov=40"] {
+            assert!(parse_quality(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn frame_pattern_escapes_percent_for_ffmpeg() {
+        let pattern = parse_frame_pattern("{stem}_100%_{index:04}").unwrap();
+        assert_eq!(pattern.ffmpeg_pattern("clip", "jpg"), "clip_100%%_%04d.jpg");
+        assert_eq!(pattern.ffmpeg_pattern("50%off", "png"), "50%%off_100%%_%04d.png");
+        assert_eq!(pattern.file_name("clip", "jpg", 7), "clip_100%_0007.jpg");
+        assert!(pattern.matches("clip_100%_0007.jpg"));
+
+        let pattern = parse_frame_pattern("f{index}.{ext}").unwrap();
+        assert_eq!(pattern.ffmpeg_pattern("clip", "webp"), "f%d.webp");
+        assert_eq!(pattern.file_name("clip", "webp", 12), "f12.webp");
+    }
+
+    #[test]
+    fn frame_pattern_needs_exactly_one_index() {
+        for value in ["{stem}", "{stem}.{ext}", "{index}_{index:03}", "{stem}_{indx}"] {
+            assert!(parse_frame_pattern(value).is_err(), "{}", value);
+        }
+        for value in ["{index:0}", "{index", "a/{index}", "a\\{index}", "{index}}"] {
+            assert!(parse_frame_pattern(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn frame_pattern_matcher_treats_glob_characters_literally() {
+        // 模板中的 glob 元字符按原样匹配
+        let pattern = parse_frame_pattern("[{stem}]*{index:03}?,").unwrap();
+        assert!(pattern.matches("[clip]*001?,.jpg"));
+        assert!(!pattern.matches("[clip]x001?,.jpg"));
+        assert!(!pattern.matches("[clip]*001x,.jpg"));
+
+        // 源文件名中的元字符由 {stem} 匹配，不影响识别
+        let pattern = parse_frame_pattern("{stem}_{index:05}").unwrap();
+        for stem in ["clip [1]", "a*b?c", "{x,y}", "50%"] {
+            assert!(pattern.matches(&pattern.file_name(stem, "png", 3)), "{}", stem);
+        }
+        assert!(!pattern.matches("clip_00003.txt"));
+        assert!(!pattern.matches("clip_abcde.png"));
+    }
+
+    #[test]
+    fn times_are_parsed_as_seconds() {
+        let cases = [
+            ("90", 90.0),
+            ("12.5", 12.5),
+            (" 7 ", 7.0),
+            ("01:30", 90.0),
+            ("1:02:03", 3723.0),
+            ("00:00:01.250", 1.25),
+            ("10:00:00.5", 36000.5),
+            ("0", 0.0),
+        ];
+        for (value, seconds) in cases {
+            assert_eq!(parse_time(value), Ok(seconds), "{}", value);
+        }
+    }
+
+    #[test]
+    fn invalid_times_are_rejected() {
+        let cases = ["", "abc", "-5", "1:60", "1:00:60", "1:2:3:4", "1.5:00", "1::2", "nan", "inf"];
+        for value in cases {
+            assert!(parse_time(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn sizes_are_parsed_with_binary_units() {
+        let cases = [
+            ("0", 0),
+            ("512", 512),
+            ("10K", 10 << 10),
+            ("10M", 10 << 20),
+            ("10m", 10 << 20),
+            ("10MB", 10 << 20),
+            ("1.5G", 3 << 29),
+            (" 2T ", 2 << 40),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(parse_size(value), Ok(bytes), "{}", value);
+        }
+    }
+
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        for value in ["", "M", "ten", "-1M", "10X", "1.5.0G", "10MM", "nanG", "infK"] {
+            assert!(parse_size(value).is_err(), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+// 并行处理一批视频：调度顺序、进度条、输出大小限制和失败记录
+
+use anyhow::Result;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use walkdir::WalkDir;
+
+use crate::api::ExtractOptions;
+use crate::args::{format_size, Args, ProgressFormat, Schedule};
+use crate::error::{BatchFailed, FailureKind};
+use crate::extract::{process_video, VideoResult};
+use crate::output::{output_dir_for, OutputPlan};
+use crate::probe::{ffprobe_path, MediaCache};
+use crate::report::JsonEvent;
+use crate::state::StateIndex;
+use crate::CANCELLED;
+
+// 本次运行新写入的输出字节数，及是否已达到 --max-output-size：达到后不再开始新的视频，正在处理的视频照常完成
+pub(crate) static OUTPUT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) static BUDGET_EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+// --keep-going 模式下记录的失败视频
+pub(crate) struct FailedVideo {
+    pub(crate) path: PathBuf,
+    pub(crate) output_dir: Option<PathBuf>,
+    pub(crate) category: &'static str,
+    /// 错误链，由外到内
+    pub(crate) chain: Vec<String>,
+    pub(crate) elapsed: Duration,
+}
+
+impl FailedVideo {
+    pub(crate) fn new(
+        path: &Path,
+        err: &anyhow::Error,
+        args: &ExtractOptions,
+        plan: &OutputPlan,
+        elapsed: Duration,
+    ) -> Self {
+        FailedVideo {
+            path: path.to_path_buf(),
+            output_dir: output_dir_for(path, args, plan).ok(),
+            elapsed,
+            category: err
+                .downcast_ref::<FailureKind>()
+                .map_or("其他错误", FailureKind::category),
+            chain: err.chain().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+// 并行处理一批视频，结果和失败分别追加到 results 和 failures；
+// 未启用 --keep-going 时遇到第一个失败即返回错误
+pub(crate) fn process_batch(
+    video_paths: &[PathBuf],
+    cli: &Args,
+    args: &ExtractOptions,
+    batch: Batch<'_>,
+    outcome: &BatchResults,
+) -> Result<()> {
+    let json = cli.progress_format == ProgressFormat::Json;
+    let progress = if cli.no_progress || cli.quiet || json {
+        None
+    } else {
+        Some(new_progress_bar(video_paths.len() as u64)?)
+    };
+    let in_flight = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+    let total = video_paths.len();
+
+    // 并行处理视频文件；par_bridge 按顺序把下一个视频交给空闲线程，使调度顺序即开始处理的顺序
+    let ordered = schedule_order(video_paths, args, batch.cache);
+    let result = ordered.into_iter().par_bridge().try_for_each(|video_path| {
+        if CANCELLED.load(Ordering::SeqCst) || BUDGET_EXCEEDED.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let running = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(pb) = &progress {
+            pb.set_prefix(format!("进行中 {}", running));
+        }
+
+        if json {
+            JsonEvent::Started { path: video_path.to_string_lossy() }.emit();
+        }
+        let video_started = Instant::now();
+        let result = process_video(video_path, args, batch);
+        let elapsed = video_started.elapsed();
+
+        // 被中断的视频既不算完成也不算失败，其临时输出已在 process_video 中删除
+        let cancelled = result.as_ref().is_err_and(|e| {
+            matches!(e.downcast_ref::<FailureKind>(), Some(FailureKind::Cancelled))
+        });
+        if cancelled {
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            return Ok(());
+        }
+
+        let running = in_flight.fetch_sub(1, Ordering::SeqCst) - 1;
+        let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+        if json {
+            JsonEvent::finished(video_path, &result, elapsed).emit();
+        }
+        if let Some(pb) = &progress {
+            // 暂停进度条输出错误，避免与进度条刷新交错
+            if let Err(e) = &result {
+                pb.suspend(|| error!(path = %video_path.display(), "处理失败: {:#}", e));
+            }
+            pb.set_prefix(format!("进行中 {}", running));
+            pb.set_message(format!(
+                "最近完成: {}",
+                video_path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            pb.inc(1);
+        } else {
+            let status = if result.is_ok() { "完成" } else { "失败" };
+            info!("[{}/{}] {}: {}", done, total, status, video_path.display());
+        }
+
+        match result {
+            Ok(mut video_result) => {
+                video_result.elapsed = elapsed;
+                if !video_result.skipped {
+                    track_output_size(&video_result.output_dir, args);
+                }
+                outcome.results.lock().unwrap().push(video_result);
+                Ok(())
+            }
+            Err(e) => {
+                let failed = FailedVideo::new(video_path, &e, args, batch.plan, elapsed);
+                outcome.failures.lock().unwrap().push(failed);
+                if !args.keep_going {
+                    return Err(e.context(BatchFailed::First(video_path.to_path_buf())));
+                }
+                if progress.is_none() {
+                    error!(path = %video_path.display(), "处理失败: {:#}", e);
+                }
+                Ok(())
+            }
+        }
+    });
+
+    if let Some(pb) = &progress {
+        if result.is_ok() && !CANCELLED.load(Ordering::SeqCst) {
+            pb.finish();
+        } else {
+            pb.abandon();
+        }
+    }
+    result
+}
+
+// 开始前检查输出所在磁盘的可用空间：不足 --max-output-size 时中止；
+// 未指定上限时以输入视频总大小粗略估计输出量，可用空间更小时只警告
+pub(crate) fn check_disk_space(video_paths: &[PathBuf], args: &ExtractOptions, cache: &MediaCache) -> Result<()> {
+    let input_bytes: u64 = video_paths
+        .iter()
+        .filter_map(|path| cache.file_stat(path))
+        .map(|stat| stat.size)
+        .sum();
+    // 输出目录可能尚未创建，查询最近的已存在上级目录所在的磁盘
+    let Some(existing) = args.output.ancestors().find(|dir| dir.is_dir()) else {
+        return Ok(());
+    };
+    let available = match fs2::available_space(existing) {
+        Ok(available) => available,
+        Err(e) => {
+            warn!("无法获取 {} 所在磁盘的可用空间: {}", existing.display(), e);
+            return Ok(());
+        }
+    };
+    info!(
+        "输入视频共 {}，输出所在磁盘可用空间 {}",
+        format_size(input_bytes),
+        format_size(available)
+    );
+    match args.max_output_size {
+        Some(budget) if available < budget => anyhow::bail!(
+            "输出所在磁盘可用空间 ({}) 小于 --max-output-size ({})",
+            format_size(available),
+            format_size(budget)
+        ),
+        Some(_) => {}
+        None if available < input_bytes => warn!(
+            "输出所在磁盘可用空间 ({}) 小于输入视频总大小 ({})，可能不足以保存全部输出；可用 --max-output-size 限制输出总量",
+            format_size(available),
+            format_size(input_bytes)
+        ),
+        None => {}
+    }
+    Ok(())
+}
+
+// 累计新写入的输出大小，超过 --max-output-size 后置位 BUDGET_EXCEEDED 停止调度
+fn track_output_size(output: &Path, args: &ExtractOptions) {
+    let Some(budget) = args.max_output_size else {
+        return;
+    };
+    let bytes = output_size(output);
+    let total = OUTPUT_BYTES.fetch_add(bytes, Ordering::SeqCst) + bytes;
+    if total > budget && !BUDGET_EXCEEDED.swap(true, Ordering::SeqCst) {
+        warn!(
+            "新写入的输出 ({}) 已超过 --max-output-size ({})，不再开始新的视频",
+            format_size(total),
+            format_size(budget)
+        );
+    }
+}
+
+// 输出文件 (--poster-only) 或输出目录中所有文件的总大小
+fn output_size(output: &Path) -> u64 {
+    WalkDir::new(output)
+        .into_iter()
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// 按 --schedule 排列处理顺序，大文件或长视频先开始；时长未知的视频按0处理，相同时按路径
+fn schedule_order<'a>(
+    video_paths: &'a [PathBuf],
+    args: &ExtractOptions,
+    cache: &MediaCache,
+) -> Vec<&'a PathBuf> {
+    let mut ordered: Vec<&PathBuf> = video_paths.iter().collect();
+    match args.schedule {
+        Schedule::None => {}
+        Schedule::Name => ordered.sort(),
+        Schedule::Size => ordered.sort_by_cached_key(|p| {
+            (std::cmp::Reverse(cache.file_stat(p).map_or(0, |s| s.size)), p.to_path_buf())
+        }),
+        Schedule::Duration => {
+            let ffprobe = ffprobe_path(args);
+            let durations: HashMap<&PathBuf, f64> = ordered
+                .par_iter()
+                .map(|p| (*p, cache.duration(&ffprobe, p).unwrap_or(0.0)))
+                .collect();
+            ordered.sort_by(|a, b| durations[b].total_cmp(&durations[a]).then_with(|| a.cmp(b)));
+        }
+    }
+    ordered
+}
+
+// 创建总体进度条：已完成/总数、进行中数量、耗时与剩余时间估计
+fn new_progress_bar(total: u64) -> Result<ProgressBar> {
+    let pb = ProgressBar::new(total);
+    pb.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} ({prefix}, 剩余 {eta}) {msg}",
+        )?
+        .progress_chars("=>-"),
+    );
+    pb.set_prefix("进行中 0");
+    Ok(pb)
+}
+
+// 同一批视频共用的输出目录分配、缓存和状态文件
+#[derive(Clone, Copy)]
+pub(crate) struct Batch<'a> {
+    pub(crate) plan: &'a OutputPlan,
+    pub(crate) cache: &'a MediaCache,
+    pub(crate) state: Option<&'a StateIndex>,
+}
+
+// 已处理视频的结果和失败，并行处理时通过互斥锁追加
+#[derive(Default)]
+pub(crate) struct BatchResults {
+    pub(crate) results: Mutex<Vec<VideoResult>>,
+    pub(crate) failures: Mutex<Vec<FailedVideo>>,
+}
@@ -0,0 +1,558 @@
+// 命令行入口：解析参数、初始化日志，按顺序执行扫描、筛选、提取和汇总，并换算为进程退出码
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use rayon::prelude::*;
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+
+use crate::api::ExtractOptions;
+use crate::args::{
+    format_size, prepare_args, Args, ExtractMode, OutputFormat, PictType, ProgressFormat, Schedule,
+};
+use crate::batch::{
+    check_disk_space, process_batch, Batch, BatchResults, FailedVideo, BUDGET_EXCEEDED,
+    OUTPUT_BYTES,
+};
+use crate::config::with_config_args;
+use crate::discovery::{
+    input_list_source, read_input_list, scan_input_dir, sort_videos, PathFilters, ScanError,
+};
+use crate::error::BatchFailed;
+use crate::extract::{extraction_window, position_percents, VideoResult};
+use crate::ffmpeg::video_filter;
+use crate::filter::{
+    filter_by_metadata, filter_recent, needs_probe_filter, validate_videos, FilteredVideo,
+    InvalidVideo,
+};
+use crate::output::{
+    archive_path, has_output, is_complete, output_dir_for, poster_path, remove_stale_temp_dirs,
+    resolve_collisions, ExistingOutput, OutputPlan,
+};
+use crate::probe::{
+    check_encoder, check_ffmpeg_available, check_ffprobe_available, check_tonemap_filter,
+    ffprobe_path, print_probe_report, MediaCache,
+};
+use crate::report::{
+    print_failure_summary, print_summary, write_manifest, write_report, JsonEvent, ReportFormat,
+    RUN_MANIFEST,
+};
+use crate::state::StateIndex;
+use crate::watch::watch_input;
+use crate::CANCELLED;
+
+// 有视频处理失败时的退出码 (--keep-going 时为全部处理完之后，否则为第一个失败时)
+const EXIT_VIDEOS_FAILED: i32 = 1;
+
+// 参数校验失败、找不到FFmpeg、读写输出目录失败等无法正常运行时的退出码，与 clap 的参数错误一致
+const EXIT_SETUP_ERROR: i32 = 2;
+
+// 被 Ctrl-C 中断时的退出码 (128 + SIGINT)
+const EXIT_INTERRUPTED: i32 = 130;
+
+// --dry-run 时没有任何视频需要处理的退出码，便于脚本跳过空运行
+const EXIT_NOTHING_TO_DO: i32 = 3;
+
+// 输出达到 --max-output-size 后停止调度、仍有视频未处理时的退出码
+const EXIT_BUDGET_EXCEEDED: i32 = 4;
+
+// 一次运行的全部结果，用于打印汇总和写入报告
+pub(crate) struct RunSummary {
+    pub(crate) results: Vec<VideoResult>,
+    pub(crate) failures: Vec<FailedVideo>,
+    pub(crate) invalid: Vec<InvalidVideo>,
+    pub(crate) filtered: Vec<FilteredVideo>,
+    pub(crate) scan_errors: Vec<ScanError>,
+    pub(crate) elapsed: Duration,
+}
+
+// 按 -v/-vv/--quiet 初始化日志，日志输出到 stderr，提取结果汇总仍输出到 stdout；
+// -v 只显示本程序的调试信息，依赖库的日志在 -vv 时才显示。指定 --log-file 时另写一份到文件
+fn init_logging(args: &Args) -> Result<()> {
+    let level = if args.quiet {
+        LevelFilter::ERROR
+    } else {
+        match args.verbose {
+            0 => LevelFilter::INFO,
+            1 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        }
+    };
+    let targets = |level: LevelFilter| {
+        let others = if level == LevelFilter::TRACE { level } else { level.min(LevelFilter::WARN) };
+        Targets::new()
+            .with_target(env!("CARGO_CRATE_NAME"), level)
+            .with_default(others)
+    };
+
+    let stderr_layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .with_filter(targets(level));
+
+    let file_layer = match &args.log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("打开日志文件失败: {:?}", path))?;
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(Mutex::new(file))
+                .with_ansi(false)
+                .with_target(false)
+                .with_filter(targets(level.max(LevelFilter::DEBUG)));
+            Some(layer)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr_layer)
+        .with(file_layer)
+        .init();
+    Ok(())
+}
+
+/// 命令行入口：解析参数 (含程序名) 并执行，返回进程退出码
+pub fn run_cli(cli: Vec<OsString>) -> i32 {
+    match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            if e.downcast_ref::<BatchFailed>().is_some() {
+                EXIT_VIDEOS_FAILED
+            } else {
+                EXIT_SETUP_ERROR
+            }
+        }
+    }
+}
+
+// 执行一次完整的运行，返回进程退出码；返回的错误由 main 按类型换算为退出码
+pub(crate) fn run(cli: Vec<OsString>) -> Result<i32> {
+    let matches = Args::command().get_matches_from(with_config_args(cli)?);
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    init_logging(&args)?;
+    prepare_args(&mut args)?;
+    let args = args;
+    let options = ExtractOptions::from(&args);
+    if let Some(nice) = args.nice {
+        set_nice(nice);
+    }
+    // PNG 无损，质量值只映射为压缩级别
+    let quality_set = matches.value_source("quality") == Some(ValueSource::CommandLine);
+    if quality_set && args.format == OutputFormat::Png {
+        warn!("PNG 为无损格式，--quality 只影响压缩级别 (文件大小)，不影响画质");
+    }
+    let started = Instant::now();
+    let report_format = args.report.as_deref().map(ReportFormat::from_path).transpose()?;
+    if args.watch && args.input.iter().any(|input| input.is_file()) {
+        anyhow::bail!("--watch 需要输入目录，不能监视单个视频文件");
+    }
+
+    // 在扫描目录之前确认FFmpeg可用，尽早给出明确提示
+    if !args.dry_run && !args.list_only && !args.probe_only {
+        check_ffmpeg_available(&args.ffmpeg_path)?;
+        check_encoder(&args.ffmpeg_path, args.format)?;
+        check_tonemap_filter(&args.ffmpeg_path, args.tonemap)?;
+        let needs_probe = !args.no_validate
+            || position_percents(&options).is_some()
+            || needs_probe_filter(&options)
+            || args.schedule == Schedule::Duration
+            || args.poster_only;
+        if needs_probe {
+            check_ffprobe_available(&ffprobe_path(&options))?;
+        }
+    }
+
+    if args.estimate || args.probe_only {
+        check_ffprobe_available(&ffprobe_path(&options))?;
+    }
+
+    // 清理上次异常退出遗留的临时目录
+    if !args.dry_run && !args.list_only && !args.probe_only {
+        remove_stale_temp_dirs(&args.output)?;
+    }
+
+    // 初始化线程池
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build_global()?;
+
+    let filters = PathFilters::from_args(&options)?;
+    let cache = MediaCache::default();
+
+    // 指定路径列表时直接使用列表，否则扫描输入目录
+    let (mut video_paths, scan_errors) = match input_list_source(&options) {
+        Some(source) => read_input_list(&source, &options, &cache)?,
+        None => scan_input_dir(&options, &filters, &cache),
+    };
+    sort_videos(&mut video_paths, args.sort, &cache);
+    let (video_paths, recent) = filter_recent(video_paths, &options, &cache);
+
+    info!("找到 {} 个待处理视频文件", video_paths.len());
+    if !recent.is_empty() {
+        info!("跳过 {} 个最近修改过的文件 (--min-age)", recent.len());
+    }
+    if !scan_errors.is_empty() {
+        warn!("扫描不完整: {} 个路径无法访问", scan_errors.len());
+        for scan_error in &scan_errors {
+            warn!("  {}: {}", scan_error.path.display(), scan_error.reason);
+        }
+    }
+
+    // 只列出选中的文件：不预检、不创建目录也不调用FFmpeg
+    if args.list_only {
+        for video_path in &apply_limit(video_paths, args.limit) {
+            println!("{}", video_path.display());
+        }
+        return Ok(0);
+    }
+    if args.probe_only {
+        let video_paths = apply_limit(video_paths, args.limit);
+        print_probe_report(&video_paths, &options, args.manifest.as_deref())?;
+        return Ok(0);
+    }
+
+    // 并行预检，剔除无法读取或没有视频流的文件
+    let (video_paths, mut invalid) = if args.no_validate || args.dry_run {
+        (video_paths, Vec::new())
+    } else {
+        validate_videos(video_paths, &ffprobe_path(&options))
+    };
+    if !invalid.is_empty() {
+        warn!("预检: {} 个文件无法读取或没有视频流，已跳过", invalid.len());
+    }
+
+    let (video_paths, mut filtered) = if args.dry_run {
+        (video_paths, Vec::new())
+    } else {
+        filter_by_metadata(video_paths, &options, &cache)
+    };
+    if !filtered.is_empty() {
+        info!("筛选: {} 个文件不满足时长/分辨率/大小条件，已跳过", filtered.len());
+    }
+
+    filtered.extend(recent);
+    let mut plan = OutputPlan::default();
+    let (video_paths, collided) = resolve_collisions(video_paths, &options, &mut plan)?;
+    filtered.extend(collided);
+    let video_paths = apply_limit(video_paths, args.limit);
+    if args.progress_format == ProgressFormat::Json {
+        JsonEvent::Discovered { count: video_paths.len() }.emit();
+    }
+
+    match position_percents(&options) {
+        Some(percents) => info!("抽帧位置: {:?}%", percents),
+        None if args.fast_seek => info!("抽帧方式: 读取关键帧索引后输入端定位提取 (--fast-seek)"),
+        None if args.idr_only => {
+            info!("抽帧滤镜: {} (-skip_frame nokey)", video_filter(&options, None))
+        }
+        None => info!("抽帧滤镜: {}", video_filter(&options, None)),
+    }
+
+    let existing = ExistingOutput::from_args(&options);
+    let state = args
+        .state_file
+        .as_deref()
+        .map(|path| StateIndex::load(path, args.rescan))
+        .transpose()?;
+
+    // 试运行：只打印计划，不创建目录也不调用FFmpeg
+    if args.dry_run {
+        let batch = Batch { plan: &plan, cache: &cache, state: state.as_ref() };
+        if !print_dry_run(&video_paths, &options, batch, existing, args.estimate)? {
+            return Ok(EXIT_NOTHING_TO_DO);
+        }
+        return Ok(0);
+    }
+
+    check_disk_space(&video_paths, &options, &cache)?;
+
+    // 第一次 Ctrl-C 停止调度并终止正在运行的FFmpeg，打印已完成部分的汇总；第二次立即退出
+    ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        warn!("收到中断信号，正在停止 (再次按 Ctrl-C 立即退出)");
+    })
+    .context("注册 Ctrl-C 处理函数失败")?;
+
+    let total = video_paths.len();
+    let outcome = BatchResults::default();
+    let state = state.as_ref();
+    let batch = Batch { plan: &plan, cache: &cache, state };
+    let mut result = process_batch(&video_paths, &args, &options, batch, &outcome);
+    // 首批的缓存不带入监视模式，之后每批重新探测
+    drop(cache);
+
+    // 监视模式：首批完成后继续处理新出现的视频，直到 Ctrl-C
+    if args.watch && result.is_ok() && !CANCELLED.load(Ordering::SeqCst) {
+        result = watch_input(
+            &args,
+            &options,
+            &mut plan,
+            state,
+            &outcome,
+            &mut invalid,
+            &mut filtered,
+        );
+    }
+    let cancelled = CANCELLED.load(Ordering::SeqCst);
+    if let Some(state) = state {
+        state.save()?;
+    }
+
+    let mut results = outcome.results.into_inner().unwrap();
+    results.sort_by(|a, b| a.source.cmp(&b.source));
+    let summary = RunSummary {
+        results,
+        failures: outcome.failures.into_inner().unwrap(),
+        invalid,
+        filtered,
+        scan_errors,
+        elapsed: started.elapsed(),
+    };
+
+    // 报告、清单和汇总在失败时也要写出，因此先于错误返回
+    if let (Some(path), Some(format)) = (&args.report, report_format) {
+        write_report(path, format, &summary, &options)?;
+    }
+
+    let manifest_path = args
+        .manifest
+        .clone()
+        .unwrap_or_else(|| args.output.join(RUN_MANIFEST));
+    if !args.no_manifest {
+        write_manifest(&manifest_path, &summary.results)?;
+    }
+
+    if args.progress_format == ProgressFormat::Json {
+        JsonEvent::summary(&summary).emit();
+    } else if !args.quiet {
+        print_summary(&summary, &options);
+    }
+    // 不带 --keep-going 时首个失败的视频已在上面计入汇总，这里按该错误退出
+    result?;
+
+    if !summary.failures.is_empty() {
+        print_failure_summary(&summary.failures, summary.results.len());
+    }
+    if cancelled && !args.watch {
+        let finished = summary.results.len() + summary.failures.len();
+        warn!("已中断: {} 个视频中有 {} 个未处理完成", total, total - finished);
+        return Ok(EXIT_INTERRUPTED);
+    }
+    if BUDGET_EXCEEDED.load(Ordering::SeqCst) {
+        let finished = summary.results.len() + summary.failures.len();
+        if finished < total {
+            warn!(
+                "新写入的输出 ({}) 已超过 --max-output-size，{} 个视频中有 {} 个未处理",
+                format_size(OUTPUT_BYTES.load(Ordering::SeqCst)),
+                total,
+                total - finished
+            );
+            return Ok(EXIT_BUDGET_EXCEEDED);
+        }
+    }
+    if !summary.failures.is_empty() {
+        return Err(BatchFailed::Count(summary.failures.len()).into());
+    }
+
+    Ok(0)
+}
+
+// 打印试运行计划：每个视频一行 (源文件、输出目录、状态，--estimate 时加预计帧数) 及合计。
+// 返回是否有需要处理的视频
+fn print_dry_run(
+    video_paths: &[PathBuf],
+    args: &ExtractOptions,
+    batch: Batch<'_>,
+    existing: ExistingOutput,
+    estimate: bool,
+) -> Result<bool> {
+    let Batch { plan, cache, state } = batch;
+    let mut planned = Vec::new();
+    for video_path in video_paths {
+        let mut output_dir = output_dir_for(video_path, args, plan)?;
+        let (done, partial) = if args.poster_only {
+            output_dir = poster_path(&output_dir, args);
+            (output_dir.is_file(), false)
+        } else if let Some(format) = args.archive {
+            output_dir = archive_path(&output_dir, format);
+            (output_dir.is_file(), false)
+        } else {
+            let done = is_complete(&output_dir);
+            (done, !done && has_output(&output_dir, args.pattern.as_ref()))
+        };
+        let status = if let Some(state) = state {
+            match (existing, state.unchanged(video_path)) {
+                (ExistingOutput::Skip, Some(_)) => None,
+                _ => Some("待处理"),
+            }
+        } else if partial && existing == ExistingOutput::Skip {
+            Some("重新提取(上次未完成)")
+        } else if !done && !partial {
+            Some("待处理")
+        } else {
+            match existing {
+                ExistingOutput::Skip => None,
+                ExistingOutput::Overwrite => Some("覆盖(清除已有关键帧)"),
+                ExistingOutput::Force => Some("重新提取(删除已有输出)"),
+            }
+        };
+        planned.push((video_path, output_dir, status));
+    }
+
+    // 只为需要处理的视频估计帧数，ffprobe 并行调用
+    let estimates: Vec<Option<u64>> = planned
+        .par_iter()
+        .map(|(video_path, _, status)| {
+            if estimate && status.is_some() {
+                estimate_frames(video_path, args, cache)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let skip_reason = if state.is_some() {
+        "跳过(状态文件记录未变化)"
+    } else {
+        "跳过(输出目录已存在)"
+    };
+    for ((video_path, output_dir, status), frames) in planned.iter().zip(&estimates) {
+        let status = status.unwrap_or(skip_reason);
+        match (estimate, frames) {
+            (false, _) => println!("{}\t{}\t{}", video_path.display(), output_dir.display(), status),
+            (true, Some(frames)) => println!(
+                "{}\t{}\t{}\t约 {} 帧",
+                video_path.display(),
+                output_dir.display(),
+                status,
+                frames
+            ),
+            (true, None) => println!(
+                "{}\t{}\t{}\t-",
+                video_path.display(),
+                output_dir.display(),
+                status
+            ),
+        }
+    }
+
+    let todo = planned.iter().filter(|(_, _, status)| status.is_some()).count();
+    let mut totals = format!("\n计划处理 {} 个视频，跳过 {} 个", todo, planned.len() - todo);
+    if estimate {
+        let known: u64 = estimates.iter().flatten().sum();
+        let unknown = planned
+            .iter()
+            .zip(&estimates)
+            .filter(|((_, _, status), estimate)| status.is_some() && estimate.is_none())
+            .count();
+        totals.push_str(&format!("，预计约 {} 帧", known));
+        if unknown > 0 {
+            totals.push_str(&format!(" ({} 个视频无法估计)", unknown));
+        }
+    }
+    println!("{}", totals);
+    Ok(todo > 0)
+}
+
+// 估计一个视频将提取的帧数：固定位置模式为位置数，间隔模式按时长计算，关键帧模式统计关键帧数；
+// 场景模式取决于画面内容，无法估计
+fn estimate_frames(video_path: &Path, args: &ExtractOptions, cache: &MediaCache) -> Option<u64> {
+    if args.poster_only {
+        return Some(1);
+    }
+    let ffprobe = ffprobe_path(args);
+    let frames = if let Some(percents) = position_percents(args) {
+        percents.len() as u64
+    } else {
+        match args.mode {
+            ExtractMode::Scene => return None,
+            ExtractMode::Interval => {
+                let duration = cache.duration(&ffprobe, video_path).ok()?;
+                let window = extraction_window(duration, args);
+                (window / args.interval_secs).ceil() as u64
+            }
+            // 只统计关键帧，包含P/B帧时无法估计
+            ExtractMode::Keyframe if args.pict_types.iter().any(|&t| t != PictType::I) => {
+                return None
+            }
+            ExtractMode::Keyframe => count_keyframes_probe(&ffprobe, video_path, args).ok()?,
+        }
+    };
+    Some(apply_frame_caps(frames, args))
+}
+
+// 按 --every 和 --max-frames 修正帧数
+fn apply_frame_caps(frames: u64, args: &ExtractOptions) -> u64 {
+    let frames = match args.every.filter(|&k| k > 1) {
+        Some(every) => frames.div_ceil(every),
+        None => frames,
+    };
+    args.max_frames.map_or(frames, |max| frames.min(max))
+}
+
+// 用 ffprobe 只解码关键帧并计数，遵循 --start/--end 范围
+pub(crate) fn count_keyframes_probe(ffprobe: &Path, video_path: &Path, args: &ExtractOptions) -> Result<u64> {
+    let mut command = Command::new(ffprobe);
+    command.args(["-v", "error", "-select_streams", "v:0", "-skip_frame", "nokey"]);
+    if args.start.is_some() || args.end.is_some() {
+        let start = args.start.map(|s| s.to_string()).unwrap_or_default();
+        let end = args.end.map(|e| e.to_string()).unwrap_or_default();
+        command.args(["-read_intervals", &format!("{}%{}", start, end)]);
+    }
+    let output = command
+        .args(["-count_frames", "-show_entries", "stream=nb_read_frames"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(video_path)
+        .output()
+        .context("执行ffprobe命令失败")?;
+    if !output.status.success() {
+        anyhow::bail!("ffprobe返回错误状态: {}", output.status);
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.trim()
+        .parse()
+        .with_context(|| format!("无法解析关键帧数: {:?}", text.trim()))
+}
+
+// 按 --limit 截断最终列表，截掉的文件不计入汇总
+fn apply_limit(mut video_paths: Vec<PathBuf>, limit: Option<usize>) -> Vec<PathBuf> {
+    if let Some(limit) = limit.filter(|&limit| limit < video_paths.len()) {
+        info!("--limit: 只处理前 {} 个视频，其余 {} 个不处理", limit, video_paths.len() - limit);
+        video_paths.truncate(limit);
+    }
+    video_paths
+}
+
+// 设置整个进程的 nice 值，之后启动的子进程都会继承；失败只警告，不影响提取
+#[cfg(unix)]
+fn set_nice(nice: i32) {
+    // SAFETY: setpriority 只修改当前进程的调度优先级
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+    if result == 0 {
+        info!("进程优先级已降低 (nice {})", nice);
+    } else {
+        warn!("设置 nice {} 失败: {}", nice, std::io::Error::last_os_error());
+    }
+}
+
+#[cfg(not(unix))]
+fn set_nice(nice: i32) {
+    warn!("当前平台不支持 --nice，忽略 nice {}", nice);
+}
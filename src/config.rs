@@ -0,0 +1,140 @@
+// 配置文件 (vid.toml)：把其中的选项转换为命令行参数
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::CommandFactory;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::args::Args;
+
+// 默认配置文件名，未指定 --config 时在当前目录查找
+const DEFAULT_CONFIG: &str = "vid.toml";
+
+// 读取配置文件并将其中的选项转换为命令行参数，插在用户参数之前。
+// 命令行 (或环境变量) 已给出的选项及与其冲突的选项不会再从配置文件添加，
+// 因此优先级为 命令行 > 配置文件 > 内置默认值；配置值经过与命令行相同的解析和校验
+pub(crate) fn with_config_args(cli: Vec<OsString>) -> Result<Vec<OsString>> {
+    let mut command = Args::command();
+    command.build();
+    let matches = command.clone().ignore_errors(true).get_matches_from(&cli);
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => path.clone(),
+        None if Path::new(DEFAULT_CONFIG).is_file() => PathBuf::from(DEFAULT_CONFIG),
+        None => return Ok(cli),
+    };
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("读取配置文件失败: {:?}", path))?;
+    let table: toml::Table = content
+        .parse()
+        .with_context(|| format!("解析配置文件失败: {:?}", path))?;
+
+    let mut merged = vec![cli.first().cloned().unwrap_or_else(|| "vid".into())];
+    for (key, value) in &table {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str() || arg.get_long() == Some(key.as_str()))
+            .filter(|arg| arg.get_id() != "config")
+            .with_context(|| format!("配置文件 {:?} 中有未知选项: {}", path, key))?;
+        let explicit = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        let id = arg.get_id().as_str();
+        let conflicts = |a: &clap::Arg, b: &clap::Arg| {
+            command
+                .get_arg_conflicts_with(a)
+                .iter()
+                .any(|other| other.get_id() == b.get_id())
+        };
+        let overridden = explicit(id)
+            || command.get_arguments().any(|other| {
+                explicit(other.get_id().as_str()) && (conflicts(arg, other) || conflicts(other, arg))
+            });
+        if overridden {
+            continue;
+        }
+        let long = arg.get_long().unwrap_or(id);
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            single => vec![single],
+        };
+        for value in values {
+            let text = match value {
+                toml::Value::Boolean(false) if !arg.get_action().takes_values() => continue,
+                toml::Value::Boolean(true) if !arg.get_action().takes_values() => {
+                    merged.push(format!("--{}", long).into());
+                    continue;
+                }
+                toml::Value::String(text) => text.clone(),
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                    value.to_string()
+                }
+                _ => anyhow::bail!("配置文件 {:?} 中选项 {} 的值类型不支持", path, key),
+            };
+            merged.push(format!("--{}={}", long, text).into());
+        }
+    }
+    merged.extend(cli.into_iter().skip(1));
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{ExtractMode, OutputFormat};
+    use clap::Parser;
+
+    // 写入临时配置文件，以 --config 指向它并加上 cli 中的参数，返回合并后解析出的参数
+    fn parse_with_config(name: &str, config: &str, cli: &[&str]) -> Result<Args> {
+        let dir = std::env::temp_dir().join(format!("vid-config-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(DEFAULT_CONFIG);
+        std::fs::write(&path, config).unwrap();
+        let mut args: Vec<OsString> = vec!["vid".into(), "--config".into(), path.into()];
+        args.extend(cli.iter().map(OsString::from));
+        let merged = with_config_args(args);
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(Args::try_parse_from(merged?)?)
+    }
+
+    #[test]
+    fn command_line_overrides_config_which_overrides_defaults() {
+        let config = "threads = 3\nmode = \"interval\"\noutput = \"/frames\"\n";
+        let args = parse_with_config("priority", config, &["-i", "/videos", "-t", "5"]).unwrap();
+        assert_eq!(args.threads, 5);
+        assert_eq!(args.mode, ExtractMode::Interval);
+        assert_eq!(args.output, Path::new("/frames"));
+        assert_eq!(args.format, OutputFormat::Jpg);
+
+        // 与命令行选项冲突的配置项不再添加
+        let config = "input-list = \"/videos.txt\"\n";
+        let args = parse_with_config("conflict", config, &["-i", "/videos"]).unwrap();
+        assert_eq!(args.input, [Path::new("/videos")]);
+        assert_eq!(args.input_list, None);
+    }
+
+    #[test]
+    fn config_booleans_and_lists() {
+        let config = "flatten = true\nskip_hidden = false\ninclude = [\"*.mp4\", \"CAM?_*\"]\n";
+        let args = parse_with_config("values", config, &["-i", "/videos"]).unwrap();
+        assert!(args.flatten);
+        assert!(!args.skip_hidden);
+        assert_eq!(args.include, ["*.mp4", "CAM?_*"]);
+
+        // 命令行给出的列表替换配置中的列表，而不是追加
+        let args = parse_with_config("list", config, &["-i", "/videos", "--include", "*.mkv"]);
+        assert_eq!(args.unwrap().include, ["*.mkv"]);
+    }
+
+    #[test]
+    fn unknown_or_unsupported_config_values_are_rejected() {
+        let error = parse_with_config("unknown", "thread = 3\n", &["-i", "/videos"]).unwrap_err();
+        assert!(error.to_string().contains("未知选项: thread"), "{}", error);
+        assert!(parse_with_config("config", "config = \"other.toml\"\n", &[]).is_err());
+        assert!(parse_with_config("table", "[mode]\nvalue = 1\n", &["-i", "/v"]).is_err());
+        assert!(parse_with_config("invalid", "threads = \"many\"\n", &["-i", "/v"]).is_err());
+    }
+}
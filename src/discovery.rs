@@ -0,0 +1,405 @@
+// 查找待处理的视频：扫描输入目录、读取路径列表，以及 --include/--exclude 等路径规则
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::ffi::{OsStr, OsString};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::api::ExtractOptions;
+use crate::args::SortKey;
+use crate::output::relative_to_input;
+use crate::probe::MediaCache;
+
+// 扫描输入目录时无法访问的路径 (如权限不足)，其下的文件未被发现
+pub(crate) struct ScanError {
+    pub(crate) path: PathBuf,
+    pub(crate) reason: String,
+}
+
+// 支持的视频格式列表：去掉空白和开头的点 (".mp4" 与 "mp4" 等价)，忽略空项，避免空项匹配没有扩展名的文件
+pub(crate) fn get_video_extensions(exts: &str) -> Vec<String> {
+    exts.split(',')
+        .map(|s| s.trim().trim_start_matches('.').trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+// 扫描输入目录获取所有视频文件路径，匹配 --exclude 的目录整体跳过；
+// 无法访问的目录不中断扫描，收集起来在扫描结束和汇总时提示
+pub(crate) fn scan_input_dir(
+    args: &ExtractOptions,
+    filters: &PathFilters,
+    cache: &MediaCache,
+) -> (Vec<PathBuf>, Vec<ScanError>) {
+    let mut video_paths = Vec::new();
+    let mut scan_errors = Vec::new();
+    // 同一文件可能经由多个输入目录 (如互相包含)、符号链接或硬链接被多次发现，只保留第一次
+    let mut seen = std::collections::HashSet::new();
+    for root in input_roots(args) {
+        // 直接指定的视频文件不扫描，也不按扩展名和 --include/--exclude 筛选
+        let found = if root.is_file() {
+            debug!("发现: {}", root.display());
+            vec![root.clone()]
+        } else {
+            scan_root(root, args, filters, cache, &mut scan_errors)
+        };
+        for path in found {
+            if seen.insert(file_id(&path, cache)) {
+                video_paths.push(path);
+            } else {
+                debug!("重复: {} (同一文件已经由其他路径发现)", path.display());
+            }
+        }
+    }
+    (video_paths, scan_errors)
+}
+
+// 文件身份：Unix 上为 (设备号, inode)，其他平台为规范化路径
+#[derive(PartialEq, Eq, Hash)]
+pub(crate) enum FileId {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Inode(u64, u64),
+    Path(PathBuf),
+}
+
+pub(crate) fn file_id(path: &Path, cache: &MediaCache) -> FileId {
+    match cache.file_stat(path).and_then(|stat| stat.inode) {
+        Some((dev, ino)) => FileId::Inode(dev, ino),
+        None => FileId::Path(std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())),
+    }
+}
+
+// 按 --sort 排序，保证 --limit 和日志在不同机器上可重现
+pub(crate) fn sort_videos(video_paths: &mut [PathBuf], key: SortKey, cache: &MediaCache) {
+    let stat = |p: &PathBuf| cache.file_stat(p);
+    match key {
+        SortKey::Name => video_paths.sort(),
+        SortKey::Size => video_paths.sort_by_cached_key(|p| (stat(p).map(|s| s.size), p.clone())),
+        SortKey::Mtime => {
+            video_paths.sort_by_cached_key(|p| (stat(p).and_then(|s| s.modified), p.clone()))
+        }
+    }
+}
+
+// 是否为隐藏文件或目录 (名称以 . 开头)
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+// 扫描单个输入目录，匹配 --exclude 的目录整体跳过，文件还需匹配 --include
+pub(crate) fn scan_root(
+    root: &Path,
+    args: &ExtractOptions,
+    filters: &PathFilters,
+    cache: &MediaCache,
+    scan_errors: &mut Vec<ScanError>,
+) -> Vec<PathBuf> {
+    let mut walker = WalkDir::new(root).follow_links(args.follow_symlinks);
+    if let Some(depth) = args.max_depth {
+        walker = walker.max_depth(depth);
+    }
+    walker
+        .into_iter()
+        .filter_entry(|e| {
+            if args.skip_hidden && e.depth() > 0 && is_hidden(e.file_name()) {
+                debug!("跳过 (隐藏): {}", e.path().display());
+                return false;
+            }
+            let relative = e.path().strip_prefix(root).unwrap_or(e.path());
+            let excluded = e.depth() > 0 && filters.excludes(relative, e.file_type().is_dir());
+            if excluded {
+                debug!("排除: {}", e.path().display());
+            }
+            !excluded
+        })
+        .filter_map(|e| match e {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                if let Some(ancestor) = err.loop_ancestor() {
+                    warn!(
+                        "符号链接循环: {} 指向其上级目录 {}，已跳过",
+                        err.path().unwrap_or(root).display(),
+                        ancestor.display()
+                    );
+                    return None;
+                }
+                scan_errors.push(ScanError {
+                    path: err.path().map(Path::to_path_buf).unwrap_or_default(),
+                    reason: err
+                        .io_error()
+                        .map_or_else(|| err.to_string(), ToString::to_string),
+                });
+                None
+            }
+        })
+        .filter(|e| is_video_file(e.path(), args) && cache.file_stat(e.path()).is_some())
+        .filter(|e| {
+            let relative = e.path().strip_prefix(root).unwrap_or(e.path());
+            let included = filters.includes(relative);
+            if !included {
+                debug!("跳过 (不匹配 --include): {}", e.path().display());
+            }
+            included
+        })
+        .map(|e| e.path().to_path_buf())
+        .inspect(|path| debug!("发现: {}", path.display()))
+        .collect()
+}
+
+// 路径列表的来源：--input-list 文件，或 --input - (--input-list -) 表示的标准输入
+pub(crate) enum ListSource {
+    File(PathBuf),
+    Stdin,
+}
+
+pub(crate) fn input_list_source(args: &ExtractOptions) -> Option<ListSource> {
+    match (&args.input_list, args.input.as_slice()) {
+        (Some(path), _) if path.as_os_str() == "-" => Some(ListSource::Stdin),
+        (Some(path), _) => Some(ListSource::File(path.clone())),
+        (None, [input]) if input.as_os_str() == "-" => Some(ListSource::Stdin),
+        _ => None,
+    }
+}
+
+// 要扫描的输入目录，也是输出目录结构的基准；使用路径列表时为空
+pub(crate) fn input_roots(args: &ExtractOptions) -> &[PathBuf] {
+    if input_list_source(args).is_some() {
+        &[]
+    } else {
+        &args.input
+    }
+}
+
+// 视频所属的输入目录及其相对路径；输入目录互相包含时取第一个匹配的
+pub(crate) fn root_for<'a>(path: &Path, args: &'a ExtractOptions) -> Option<(&'a Path, PathBuf)> {
+    input_roots(args)
+        .iter()
+        .find_map(|root| Some((root.as_path(), relative_to_input(path, root)?)))
+}
+
+// 读取路径列表，每行一个路径 (去除首尾空白)，跳过空行和 # 注释。
+// 不存在的路径按行号报告：--keep-going 时跳过并记入扫描错误，否则报错退出；URL 不做检查
+pub(crate) fn read_input_list(
+    source: &ListSource,
+    args: &ExtractOptions,
+    cache: &MediaCache,
+) -> Result<(Vec<PathBuf>, Vec<ScanError>)> {
+    let content = match source {
+        ListSource::File(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("读取路径列表失败: {:?}", path))?,
+        ListSource::Stdin => {
+            let mut content = String::new();
+            std::io::stdin()
+                .read_to_string(&mut content)
+                .context("从标准输入读取路径列表失败")?;
+            content
+        }
+    };
+
+    let mut video_paths = Vec::new();
+    let mut missing = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = PathBuf::from(line);
+        // URL 直接交给 FFmpeg 打开，不检查本地文件和扩展名
+        if is_url(&path) {
+            debug!("发现 URL: {}", line);
+            video_paths.push(path);
+            continue;
+        }
+        if cache.file_stat(&path).is_none() {
+            missing.push(ScanError {
+                path,
+                reason: format!("第 {} 行: 文件不存在", number + 1),
+            });
+            continue;
+        }
+        if !args.no_ext_filter && !is_video_file(&path, args) {
+            debug!("跳过 (扩展名不匹配): {}", path.display());
+            continue;
+        }
+        debug!("发现: {}", path.display());
+        video_paths.push(path);
+    }
+
+    if !missing.is_empty() && !args.keep_going {
+        let lines: Vec<String> = missing
+            .iter()
+            .map(|m| format!("{}: {}", m.reason, m.path.display()))
+            .collect();
+        anyhow::bail!("路径列表中有 {} 个文件不存在:\n  {}", missing.len(), lines.join("\n  "));
+    }
+    Ok((video_paths, missing))
+}
+
+// 路径列表中的 http:// 或 https:// 条目视为远程视频
+pub(crate) fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|s| {
+        let lower = s.to_ascii_lowercase();
+        lower.starts_with("http://") || lower.starts_with("https://")
+    })
+}
+
+// URL 最后一段路径去掉扩展名后的文件名，不安全的字符替换为 '_'；
+// 去掉查询参数和锚点，路径为空时使用主机名
+fn url_stem(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let segment = rest.rsplit('/').find(|s| !s.is_empty()).unwrap_or_default();
+    let stem = match segment.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && rest.contains('/') => stem,
+        _ => segment,
+    };
+    let sanitized: String = percent_decode(stem)
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    let sanitized = sanitized.trim_matches('.');
+    if sanitized.is_empty() { "url".to_string() } else { sanitized.to_string() }
+}
+
+// 解码 URL 中的 %XX 转义，无效的转义原样保留
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.filter(|_| bytes[i] == b'%').and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// 输出目录和文件名模板使用的视频名：本地文件取去掉扩展名的文件名，URL 取 url_stem
+pub(crate) fn video_stem(video_path: &Path) -> Option<OsString> {
+    match video_path.to_str().filter(|_| is_url(video_path)) {
+        Some(url) => Some(url_stem(url).into()),
+        None => video_path.file_stem().map(OsStr::to_os_string),
+    }
+}
+
+// 扩展名是否在 --extensions 列表中 (不区分大小写)
+pub(crate) fn is_video_file(path: &Path, args: &ExtractOptions) -> bool {
+    let ext = path
+        .extension()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    get_video_extensions(&args.extensions).contains(&ext)
+}
+
+// 路径是否超出 --max-depth、位于匹配 --exclude 的目录或隐藏目录中、或不匹配 --include (与首次扫描的规则一致)
+pub(crate) fn is_excluded(path: &Path, args: &ExtractOptions, filters: &PathFilters) -> bool {
+    let relative = root_for(path, args).map_or_else(|| path.to_path_buf(), |(_, relative)| relative);
+    if args.max_depth.is_some_and(|depth| relative.components().count() > depth) {
+        return true;
+    }
+    let excluded = relative
+        .ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .any(|p| {
+            filters.exclude.is_match(p)
+                || (args.skip_hidden && p.file_name().is_some_and(is_hidden))
+        });
+    excluded || !filters.includes(&relative)
+}
+
+// --include / --exclude 编译后的模式集合
+pub(crate) struct PathFilters {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl PathFilters {
+    pub(crate) fn from_args(args: &ExtractOptions) -> Result<Self> {
+        Ok(Self {
+            include: build_globset(&args.include)?,
+            exclude: build_globset(&args.exclude)?,
+        })
+    }
+
+    // 目录也用带结尾分隔符的形式匹配，使 "**/trash/**" 能在进入 trash 目录之前将其整体剪除
+    fn excludes(&self, relative: &Path, is_dir: bool) -> bool {
+        self.exclude.is_match(relative)
+            || (is_dir && self.exclude.is_match(format!("{}/", relative.to_string_lossy())))
+    }
+
+    // 未指定 --include 时全部选中；模式可以匹配相对路径，也可以只匹配文件名
+    fn includes(&self, relative: &Path) -> bool {
+        self.include.is_empty()
+            || self.include.is_match(relative)
+            || relative.file_name().is_some_and(|name| self.include.is_match(name))
+    }
+}
+
+// 编译 glob 模式集合，Windows 上不区分大小写
+fn build_globset(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(cfg!(windows))
+            .build()
+            .with_context(|| format!("无效的glob模式: {}", pattern))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn video_extensions_are_normalized() {
+        assert_eq!(get_video_extensions(" .MP4,,mkv , .Avi"), ["mp4", "mkv", "avi"]);
+        assert_eq!(get_video_extensions(". mov"), ["mov"]);
+        assert!(get_video_extensions(" , ., ").is_empty());
+    }
+
+    #[test]
+    fn url_stems_ignore_queries_and_trailing_slashes() {
+        let cases = [
+            ("https://cdn.example.com/videos/clip.mp4", "clip"),
+            ("https://cdn.example.com/videos/clip.mp4?token=a.b&t=1#frag", "clip"),
+            ("https://cdn.example.com/videos/clip.mp4/", "clip"),
+            ("https://cdn.example.com/live/stream//", "stream"),
+            ("https://cdn.example.com/watch?v=abc.def", "watch"),
+            ("rtmp://example.com", "example.com"),
+            ("https://cdn.example.com/my%20clip.v2.mkv", "my_clip.v2"),
+            ("https://cdn.example.com/%E8%A7%86%E9%A2%91.mp4", "视频"),
+            ("https://cdn.example.com/100%.mp4", "100_"),
+            ("https://cdn.example.com/.../", "url"),
+        ];
+        for (url, stem) in cases {
+            assert_eq!(url_stem(url), stem, "{}", url);
+        }
+    }
+
+    #[test]
+    fn percent_decode_keeps_invalid_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("%e8%a7%86"), "视");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%%41"), "%A");
+        // 不完整的 UTF-8 序列按替换字符解码
+        assert_eq!(percent_decode("%E8%A7.mp4"), "\u{FFFD}.mp4");
+    }
+}
@@ -0,0 +1,96 @@
+// 错误链中用于判断失败原因和退出码的错误类型
+
+use std::fmt;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+
+// 视频处理失败的类别，用于在汇总中区分原因
+#[derive(Debug)]
+pub(crate) enum FailureKind {
+    CreateDir(PathBuf),
+    Spawn,
+    /// 退出状态及捕获到的 stderr 末尾几行
+    FfmpegStatus(ExitStatus, Vec<String>),
+    Timeout(Duration),
+    /// 收到 Ctrl-C 而中止
+    Cancelled,
+}
+
+impl FailureKind {
+    pub(crate) fn category(&self) -> &'static str {
+        match self {
+            FailureKind::CreateDir(_) => "无法创建输出目录",
+            FailureKind::Spawn => "无法启动FFmpeg",
+            FailureKind::FfmpegStatus(..) => "FFmpeg返回非零状态",
+            FailureKind::Timeout(_) => "FFmpeg超时",
+            FailureKind::Cancelled => "已中断",
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureKind::CreateDir(dir) => write!(f, "创建目录失败: {:?}", dir),
+            FailureKind::Spawn => write!(f, "执行FFmpeg命令失败"),
+            FailureKind::FfmpegStatus(status, tail) if tail.is_empty() => {
+                write!(f, "FFmpeg返回错误状态: {}", status)
+            }
+            FailureKind::FfmpegStatus(status, tail) => {
+                write!(f, "FFmpeg返回错误状态: {} ({})", status, tail.join(" / "))
+            }
+            FailureKind::Timeout(limit) => {
+                write!(f, "处理超过 {} 秒，已终止FFmpeg", limit.as_secs())
+            }
+            FailureKind::Cancelled => write!(f, "收到中断信号，已终止"),
+        }
+    }
+}
+
+impl std::error::Error for FailureKind {}
+
+// 视频处理失败，与参数和环境错误区分开，main 据此返回 EXIT_VIDEOS_FAILED
+#[derive(Debug)]
+pub(crate) enum BatchFailed {
+    // 未启用 --keep-going 时遇到的第一个失败
+    First(PathBuf),
+    // --keep-going 时全部处理完后失败的视频数
+    Count(usize),
+}
+
+impl fmt::Display for BatchFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchFailed::First(path) => write!(f, "处理失败: {:?}", path),
+            BatchFailed::Count(count) => write!(f, "{} 个视频处理失败", count),
+        }
+    }
+}
+
+impl std::error::Error for BatchFailed {}
+
+// --segment-parallel 的某个片段按 --retries 重试后仍失败；已单独重试过，不再整体重试
+#[derive(Debug)]
+pub(crate) struct SegmentFailed(pub(crate) f64, pub(crate) f64);
+
+impl fmt::Display for SegmentFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "片段 {:.3}-{:.3} 秒提取失败", self.0, self.1)
+    }
+}
+
+impl std::error::Error for SegmentFailed {}
+
+// 失败前共尝试的次数，重试过时附加在错误链最外层，汇总中显示为第一行
+#[derive(Debug)]
+pub(crate) struct Attempts(pub(crate) u32);
+
+impl fmt::Display for Attempts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "共尝试 {} 次", self.0)
+    }
+}
+
+impl std::error::Error for Attempts {}
@@ -0,0 +1,729 @@
+// 单个视频的提取流程：临时目录、重试和硬件解码回退，以及海报图、首尾帧、动画预览和固定位置抽帧
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::api::ExtractOptions;
+use crate::args::{ExtractMode, FramePattern, HwAccel, OutputFormat, PreviewFormat};
+use crate::batch::Batch;
+use crate::error::{Attempts, FailureKind, SegmentFailed};
+use crate::ffmpeg::{
+    decode_args, extra_args, frame_filter, run_command, run_ffmpeg, select_filter, source_filters,
+    thread_args, Deadline,
+};
+use crate::output::{
+    apply_on_success, archive_path, count_archived_frames, count_keyframes, ensure_dir, has_output,
+    is_complete, is_keyframe_file, move_keyframes, output_dir_for, poster_path, remove_done_marker,
+    remove_keyframes, temp_dir_for, write_archive, write_done_marker, ExistingOutput,
+};
+use crate::postprocess::{
+    dedup_frames, drop_black_frames, drop_blurry_frames, score_sharpness, write_contact_sheet,
+    write_sharpness_csv, write_timestamps_csv, CONTACT_SHEET,
+};
+use crate::probe::{ffprobe_path, known_duration, VideoInfo};
+use crate::report::write_video_manifest;
+use crate::CANCELLED;
+
+// 单个视频的处理结果，写入清单文件
+#[derive(Debug, Serialize)]
+pub(crate) struct VideoResult {
+    #[serde(serialize_with = "crate::report::serialize_path_lossy")]
+    pub(crate) source: PathBuf,
+    #[serde(serialize_with = "crate::report::serialize_path_lossy")]
+    pub(crate) output_dir: PathBuf,
+    pub(crate) frame_count: usize,
+    quality: u8,
+    mode: ExtractMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scene_threshold: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interval_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    positions: Option<Vec<f64>>,
+    /// 输出目录已存在而未重新提取
+    pub(crate) skipped: bool,
+    /// 硬件解码失败后改用软件解码完成
+    pub(crate) hwaccel_fallback: bool,
+    /// FFmpeg调用的尝试次数 (含 --retries 重试)，跳过的视频为0
+    pub(crate) attempts: u32,
+    /// 因 --skip-black 删除的黑帧数
+    pub(crate) black_dropped: usize,
+    /// 缩略图总览路径 (--contact-sheet)，未生成时为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) contact_sheet: Option<PathBuf>,
+    /// --on-success 对源视频的处理：moved:<新路径> 或 deleted，未处理时为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source_action: Option<String>,
+    /// 提取过程中 ffprobe 读取的视频时长 (秒)，只用于库接口
+    #[serde(skip)]
+    pub(crate) duration: Option<f64>,
+    #[serde(skip)]
+    pub(crate) elapsed: Duration,
+}
+
+// 单视频清单中的一帧
+#[derive(Debug, Serialize)]
+pub(crate) struct FrameEntry {
+    pub(crate) filename: String,
+    /// 帧在源视频中的时间 (秒)，无法获取时为 null
+    pub(crate) pts_seconds: Option<f64>,
+    /// 输出帧序号，从1开始
+    frame_index: usize,
+    /// 清晰度 (拉普拉斯方差)，仅 --min-sharpness 或 --sharpness-report 时计算
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sharpness: Option<f64>,
+    /// 被 --dedup 删除并入该帧的重复帧
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) duplicates: Vec<DuplicateFrame>,
+}
+
+// 被 --dedup 删除的重复帧，记录在其并入的保留帧下
+#[derive(Debug, Serialize)]
+pub(crate) struct DuplicateFrame {
+    /// 删除前的文件名
+    pub(crate) filename: String,
+    pub(crate) pts_seconds: Option<f64>,
+    /// 与保留帧的哈希汉明距离
+    pub(crate) distance: u32,
+}
+
+// --start/--end 限定后实际处理的时长 (秒)
+pub(crate) fn extraction_window(duration: f64, args: &ExtractOptions) -> f64 {
+    let start = args.start.unwrap_or(0.0);
+    let end = args.end.map_or(duration, |end| end.min(duration));
+    (end - start).max(0.0)
+}
+
+pub(crate) fn process_video(video_path: &Path, args: &ExtractOptions, batch: Batch<'_>) -> Result<VideoResult> {
+    let Batch { plan, cache, state } = batch;
+    let quality = args.quality.for_video(video_path);
+    let deadline = Deadline::from_args(args);
+    let mut existing = ExistingOutput::from_args(args);
+    let output_dir = output_dir_for(video_path, args, plan)?;
+    let finish = |output_dir: PathBuf, frame_count: usize, attempts: u32, fallback: bool| {
+        VideoResult {
+            source: video_path.to_path_buf(),
+            frame_count,
+            quality,
+            mode: args.mode,
+            scene_threshold: (args.mode == ExtractMode::Scene).then_some(args.scene_threshold),
+            interval_secs: (args.mode == ExtractMode::Interval).then_some(args.interval_secs),
+            positions: position_percents(args),
+            skipped: attempts == 0,
+            hwaccel_fallback: fallback,
+            attempts,
+            black_dropped: 0,
+            contact_sheet: Some(output_dir.join(CONTACT_SHEET)).filter(|p| p.is_file()),
+            output_dir,
+            source_action: None,
+            // 只取已探测过的结果，不为此额外调用 ffprobe
+            duration: cache.probed_duration(video_path),
+            elapsed: Duration::ZERO,
+        }
+    };
+
+    // 有状态文件时以其记录判断是否已完成：源文件未变化则跳过，否则替换已有输出重新提取
+    if let (Some(state), ExistingOutput::Skip) = (state, existing) {
+        if let Some(frame_count) = state.unchanged(video_path) {
+            debug!("跳过: {} (状态文件记录未变化)", video_path.display());
+            return Ok(finish(output_dir, frame_count, 0, false));
+        }
+        existing = ExistingOutput::Force;
+    }
+    // 只在确实需要提取时探测，跳过的视频不调用 ffprobe；探测失败时按信息未知处理
+    let probe = || {
+        cache.video_info(&ffprobe_path(args), video_path).unwrap_or_else(|e| {
+            debug!("无法读取视频信息: {}: {:#}", video_path.display(), e);
+            VideoInfo::default()
+        })
+    };
+
+    if args.poster_only {
+        let info = probe();
+        let (poster, attempts) =
+            extract_poster(video_path, &info, &output_dir, existing, args, deadline)?;
+        if let Some(state) = state.filter(|_| attempts > 0) {
+            state.record(video_path, 1)?;
+        }
+        let written = usize::from(poster.is_file());
+        let mut result = finish(poster, 1, attempts, false);
+        if attempts > 0 {
+            result.source_action = apply_on_success(video_path, written, args);
+        }
+        return Ok(result);
+    }
+
+    // --archive 时以归档文件是否存在判断：归档只在完整写出后才从 .partial 重命名而来
+    if let Some(format) = args.archive {
+        let archive = archive_path(&output_dir, format);
+        if archive.is_file() && existing == ExistingOutput::Skip {
+            debug!("跳过: {} (归档已存在)", video_path.display());
+            let frame_count = count_archived_frames(&archive, format, args.pattern.as_ref())?;
+            return Ok(finish(archive, frame_count, 0, false));
+        }
+    }
+
+    // 以完成标记判断是否完成，而不是目录是否存在：目录可能由其他线程或进程刚刚创建，
+    // 也可能是上次中断留下的部分输出。部分输出按 --overwrite 的方式清除关键帧后重新提取
+    let exists = has_output(&output_dir, args.pattern.as_ref());
+    if is_complete(&output_dir) && existing == ExistingOutput::Skip {
+        debug!("跳过: {} (输出目录已完成)", video_path.display());
+        let frame_count = count_keyframes(&output_dir, args.pattern.as_ref())?;
+        // 之前未使用 --archive 时完成的输出直接打包，不重新提取
+        if let Some(format) = args.archive {
+            let archive = write_archive(&output_dir, format, args)?;
+            return Ok(finish(archive, frame_count, 0, false));
+        }
+        return Ok(finish(output_dir, frame_count, 0, false));
+    }
+    if exists && existing == ExistingOutput::Skip {
+        info!("上次未完成，重新提取: {}", video_path.display());
+        existing = ExistingOutput::Overwrite;
+    }
+
+    // 先提取到临时目录，失败时删除，避免留下不完整的输出；
+    // FFmpeg启动失败或非零退出时按 --retries 重试，每次重试前清空临时目录
+    let temp_dir = temp_dir_for(&output_dir, video_path);
+    let info = probe();
+    let mut attempts = 0;
+    let (dropped, hwaccel_fallback) = loop {
+        attempts += 1;
+        reset_dir(&temp_dir)?;
+        match extract_with_fallback(video_path, &info, &temp_dir, args, deadline) {
+            Ok(extracted) => break extracted,
+            Err(e) if attempts <= args.retries && is_retryable(&e) => {
+                let backoff = Duration::from_secs(u64::from(attempts));
+                warn!(
+                    "第 {} 次尝试失败，{} 秒后重试: {} ({})",
+                    attempts,
+                    backoff.as_secs(),
+                    video_path.display(),
+                    e
+                );
+                let _ = std::fs::remove_dir_all(&temp_dir);
+                std::thread::sleep(backoff);
+            }
+            Err(e) => {
+                if !CANCELLED.load(Ordering::SeqCst) {
+                    save_ffmpeg_log(&temp_dir, &output_dir, args, true);
+                }
+                let _ = std::fs::remove_dir_all(&temp_dir);
+                return Err(if attempts > 1 { e.context(Attempts(attempts)) } else { e });
+            }
+        }
+    };
+    save_ffmpeg_log(&temp_dir, &output_dir, args, false);
+
+    match (exists, existing) {
+        (true, ExistingOutput::Overwrite) => {
+            // 先删除旧标记，移动中途退出时目录不会被误认为已完成
+            remove_done_marker(&output_dir)?;
+            remove_keyframes(&output_dir, args.pattern.as_ref())?;
+            move_keyframes(&temp_dir, &output_dir)?;
+        }
+        (true, _) => {
+            std::fs::remove_dir_all(&output_dir)
+                .with_context(|| format!("删除已有输出失败: {:?}", output_dir))?;
+            std::fs::rename(&temp_dir, &output_dir)
+                .with_context(|| format!("重命名输出目录失败: {:?}", output_dir))?;
+        }
+        (false, _) => {
+            // 输出目录可能已存在但没有关键帧 (如空目录)，此时把关键帧移入其中
+            if let Err(e) = std::fs::rename(&temp_dir, &output_dir) {
+                if !output_dir.is_dir() {
+                    return Err(e)
+                        .with_context(|| format!("重命名输出目录失败: {:?}", output_dir));
+                }
+                move_keyframes(&temp_dir, &output_dir)?;
+            }
+        }
+    }
+
+    let frame_count = count_keyframes(&output_dir, args.pattern.as_ref())?;
+    write_done_marker(&output_dir, video_path, frame_count)?;
+    let output_dir = match args.archive {
+        Some(format) => write_archive(&output_dir, format, args)?,
+        None => output_dir,
+    };
+    if let Some(state) = state {
+        state.record(video_path, frame_count)?;
+    }
+    let mut result = finish(output_dir, frame_count, attempts, hwaccel_fallback);
+    result.black_dropped = dropped.black;
+    // frame_count 是移入输出目录后实际统计的帧文件数
+    result.source_action = apply_on_success(video_path, frame_count, args);
+    Ok(result)
+}
+
+// 单个视频的FFmpeg日志文件名，提取时写在临时目录中
+pub(crate) const FFMPEG_LOG: &str = "ffmpeg.log";
+
+// 处理临时目录中的FFmpeg日志：失败或 --keep-logs 时保存到输出目录 (或 --log-dir)，否则删除。
+// 日志只用于排查问题，保存失败只记录警告
+fn save_ffmpeg_log(temp_dir: &Path, output_dir: &Path, args: &ExtractOptions, failed: bool) {
+    let log = temp_dir.join(FFMPEG_LOG);
+    if !failed && !args.keep_logs {
+        let _ = std::fs::remove_file(&log);
+        // 上次失败留下的日志已经过时
+        let _ = std::fs::remove_file(output_dir.join(FFMPEG_LOG));
+        return;
+    }
+    let target = match &args.log_dir {
+        Some(log_dir) => {
+            let relative = output_dir.strip_prefix(&args.output).unwrap_or(output_dir);
+            let mut name = relative.as_os_str().to_os_string();
+            name.push(".log");
+            log_dir.join(name)
+        }
+        // 成功时日志随临时目录一起移入输出目录
+        None if !failed => return,
+        None => output_dir.join(FFMPEG_LOG),
+    };
+    let saved = target
+        .parent()
+        .map_or(Ok(()), ensure_dir)
+        .and_then(|()| std::fs::copy(&log, &target).map(drop));
+    match saved {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&log);
+            if failed {
+                info!("FFmpeg完整输出已保存到 {}", target.display());
+            }
+        }
+        Err(e) => warn!("保存FFmpeg日志失败 {}: {}", target.display(), e),
+    }
+}
+
+// 提取单张海报图：先写临时文件，成功后再重命名，失败时按 --retries 重试。
+// 返回 (海报路径, FFmpeg尝试次数)，已存在而跳过时次数为0
+fn extract_poster(
+    video_path: &Path,
+    info: &VideoInfo,
+    output_dir: &Path,
+    existing: ExistingOutput,
+    args: &ExtractOptions,
+    deadline: Option<Deadline>,
+) -> Result<(PathBuf, u32)> {
+    let poster = poster_path(output_dir, args);
+    if poster.is_file() && existing == ExistingOutput::Skip {
+        debug!("跳过: {} (海报图已存在)", video_path.display());
+        return Ok((poster, 0));
+    }
+    let parent = poster.parent().unwrap_or(Path::new("."));
+    ensure_dir(parent).with_context(|| FailureKind::CreateDir(parent.to_path_buf()))?;
+
+    // 时长未知时从起点取第一个关键帧
+    let seek = match known_duration(video_path, info, args) {
+        Ok(duration) => {
+            args.start.unwrap_or(0.0) + extraction_window(duration, args) * args.poster_position / 100.0
+        }
+        Err(e) => {
+            debug!("无法获取时长，海报图取起点: {}: {:#}", video_path.display(), e);
+            args.start.unwrap_or(0.0)
+        }
+    };
+    let mut temp_name = poster.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".tmp-{}.{}", std::process::id(), args.format.extension()));
+    let temp = poster.with_file_name(temp_name);
+
+    let source = source_filters(info, args);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let mut command = Command::new(&args.ffmpeg_path);
+        command
+            .args(args.hwaccel.args())
+            .args(extra_args(&args.ffmpeg_input_args))
+            .args(["-skip_frame", "nokey"]) // 只解码关键帧，定位后的第一帧即为关键帧
+            .arg("-ss")
+            .arg(format!("{:.3}", seek))
+            .args(source.input_args)
+            .arg("-i")
+            .arg(video_path)
+            .args(["-frames:v", "1"]);
+        if let Some(filter) = frame_filter(&source, args) {
+            command.arg("-vf").arg(filter);
+        }
+        command
+            .args(["-c:v", args.format.encoder()])
+            .args(args.format.quality_args(args.quality.for_video(video_path)))
+            .args(thread_args(args))
+            .args(["-an", "-loglevel", "error", "-y"])
+            .args(extra_args(&args.ffmpeg_output_args))
+            .arg(&temp);
+        let log = temp.with_extension("log");
+        let result = run_command(&mut command, deadline, false, &log).and_then(|_| {
+            if temp.is_file() {
+                Ok(())
+            } else {
+                anyhow::bail!("FFmpeg没有输出海报帧 (定位点 {:.3} 秒之后没有关键帧)", seek)
+            }
+        });
+        let _ = std::fs::remove_file(&log);
+        match result {
+            Ok(()) => break,
+            Err(e) if attempts <= args.retries && is_retryable(&e) => {
+                warn!("第 {} 次尝试失败，重试: {} ({})", attempts, video_path.display(), e);
+                std::thread::sleep(Duration::from_secs(u64::from(attempts)));
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&temp);
+                return Err(if attempts > 1 { e.context(Attempts(attempts)) } else { e });
+            }
+        }
+    }
+    std::fs::rename(&temp, &poster).with_context(|| format!("重命名海报图失败: {:?}", poster))?;
+    Ok((poster, attempts))
+}
+
+// 删除并重新创建目录，清除上一次尝试留下的部分输出
+pub(crate) fn reset_dir(dir: &Path) -> Result<()> {
+    if dir.exists() {
+        std::fs::remove_dir_all(dir).with_context(|| format!("删除临时目录失败: {:?}", dir))?;
+    }
+    ensure_dir(dir).with_context(|| FailureKind::CreateDir(dir.to_path_buf()))
+}
+
+// FFmpeg启动失败或非零退出可能是暂时的 (如网络存储I/O错误)，值得重试
+pub(crate) fn is_retryable(err: &anyhow::Error) -> bool {
+    let transient = matches!(
+        err.downcast_ref::<FailureKind>(),
+        Some(FailureKind::Spawn | FailureKind::FfmpegStatus(..))
+    );
+    let segment_exhausted = err.downcast_ref::<SegmentFailed>().is_some();
+    transient && !segment_exhausted && !CANCELLED.load(Ordering::SeqCst)
+}
+
+// 提取帧，硬件解码出错 (如驱动异常) 导致FFmpeg非零退出时，清空目录后用软件解码重试一次。
+// 返回是否使用了软件解码回退
+fn extract_with_fallback(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    deadline: Option<Deadline>,
+) -> Result<(DroppedFrames, bool)> {
+    let e = match extract_frames(video_path, info, dir, args, args.hwaccel, deadline) {
+        Ok(dropped) => return Ok((dropped, false)),
+        Err(e) => e,
+    };
+    let ffmpeg_failed = matches!(
+        e.downcast_ref::<FailureKind>(),
+        Some(FailureKind::FfmpegStatus(..))
+    );
+    if !ffmpeg_failed || args.hwaccel == HwAccel::None {
+        return Err(e);
+    }
+    warn!("硬件解码失败，改用软件解码重试: {}", video_path.display());
+    reset_dir(dir)?;
+    let dropped = extract_frames(video_path, info, dir, args, HwAccel::None, deadline)?;
+    Ok((dropped, true))
+}
+
+// 提取后按条件删除的帧数
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DroppedFrames {
+    pub(crate) black: usize,
+    pub(crate) blurry: usize,
+}
+
+// 提取帧到指定目录，按条件删除黑帧、重复帧和模糊帧，按需以时间戳重命名并写入单视频清单
+fn extract_frames(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    deadline: Option<Deadline>,
+) -> Result<DroppedFrames> {
+    let mut frames = match position_percents(args) {
+        Some(percents) => {
+            extract_positions(video_path, info, dir, &percents, args, hwaccel, deadline)?
+        }
+        None => {
+            let pts = run_ffmpeg(video_path, info, dir, args, hwaccel, deadline)?;
+            list_frames(dir, &pts, args.pattern.as_ref())?
+        }
+    };
+    let mut dropped = DroppedFrames::default();
+    // 先删除黑帧，避免去重时以黑帧作为比较基准
+    if args.skip_black {
+        dropped.black = drop_black_frames(dir, &mut frames, args)?;
+    }
+    if args.dedup {
+        dedup_frames(dir, &mut frames, args.dedup_threshold)?;
+    }
+    if let Some(threshold) = args.min_sharpness {
+        dropped.blurry = drop_blurry_frames(dir, &mut frames, threshold)?;
+    }
+    if args.sharpness_report {
+        score_sharpness(dir, &mut frames)?;
+        write_sharpness_csv(dir, &frames)?;
+    }
+    if args.timestamp_names {
+        rename_by_timestamps(dir, args.format, &mut frames)?;
+    }
+    if args.timestamps {
+        write_timestamps_csv(dir, &frames)?;
+    }
+    if args.bookends {
+        extract_bookends(video_path, info, dir, args, hwaccel, deadline)?;
+    }
+    let sheet = match args.contact_sheet {
+        Some(layout) => write_contact_sheet(dir, &frames, layout, args)?,
+        None => None,
+    };
+    if args.sheet_only && sheet.is_some() {
+        remove_keyframes(dir, args.pattern.as_ref())?;
+    }
+    if let Some(preview) = args.preview {
+        write_preview(video_path, info, dir, preview, args, hwaccel, deadline)?;
+    }
+    if !args.no_manifest {
+        write_video_manifest(video_path, info, dir, &frames, sheet, dropped, args)?;
+    }
+    Ok(dropped)
+}
+
+// 提取首帧和尾帧。尾帧从末尾前1秒开始解码，-update 1 让后面的帧不断覆盖同一文件，最终留下最后一帧
+fn extract_bookends(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    deadline: Option<Deadline>,
+) -> Result<()> {
+    let ext = args.format.extension();
+    let source = source_filters(info, args);
+    for (name, is_last) in [("first", false), ("last", true)] {
+        let output = dir.join(format!("{}.{}", name, ext));
+        let mut command = Command::new(&args.ffmpeg_path);
+        command.args(hwaccel.args());
+        match (is_last, args.start, args.end) {
+            (false, Some(start), _) => {
+                command.arg("-ss").arg(format!("{:.3}", start));
+            }
+            (false, None, _) => {}
+            (true, start, Some(end)) => {
+                let from = (end - 1.0).max(start.unwrap_or(0.0));
+                command.arg("-ss").arg(format!("{:.3}", from));
+                command.arg("-t").arg(format!("{:.3}", end - from));
+            }
+            (true, _, None) => {
+                command.args(["-sseof", "-1"]);
+            }
+        }
+        command.args(source.input_args).arg("-i").arg(video_path);
+        if is_last {
+            command.args(["-update", "1"]);
+        } else {
+            command.args(["-frames:v", "1"]);
+        }
+        if let Some(filter) = frame_filter(&source, args) {
+            command.arg("-vf").arg(filter);
+        }
+        command
+            .args(["-c:v", args.format.encoder()])
+            .args(args.format.quality_args(args.quality.for_video(video_path)))
+            .args(thread_args(args))
+            .args(["-an", "-loglevel", "error", "-y"])
+            .arg(&output);
+        run_command(&mut command, deadline, false, &dir.join(FFMPEG_LOG))?;
+    }
+    Ok(())
+}
+
+// 动画预览最多包含的时长 (秒)
+const PREVIEW_MAX_SECS: u32 = 10;
+
+// 再对源视频做一次选帧，把选中的帧按 --preview-fps 依次播放，生成循环动画预览。
+// 固定位置模式只有少数几帧，预览改用关键帧。预览失败只警告并删除不完整的文件，中断除外
+fn write_preview(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    preview: PreviewFormat,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    deadline: Option<Deadline>,
+) -> Result<()> {
+    let select = match position_percents(args) {
+        Some(_) => "select=eq(pict_type\\,I)".to_string(),
+        None => select_filter(args),
+    };
+    let source = source_filters(info, args);
+    let source_filter = source.filter.as_ref().map(|filter| format!(",{}", filter));
+    let filter = format!(
+        "{}{},scale=min({}\\,iw):-2,setpts=N/({}*TB)",
+        select,
+        source_filter.unwrap_or_default(),
+        args.preview_width,
+        args.preview_fps
+    );
+    let output = dir.join(preview.file_name());
+
+    let mut command = Command::new(&args.ffmpeg_path);
+    command.args(hwaccel.args());
+    if let Some(start) = args.start {
+        command.arg("-ss").arg(format!("{:.3}", start));
+    }
+    if position_percents(args).is_none() {
+        command.args(decode_args(args));
+    }
+    command.args(source.input_args).arg("-i").arg(video_path);
+    if let Some(end) = args.end {
+        command.arg("-t").arg(format!("{:.3}", end - args.start.unwrap_or(0.0)));
+    }
+    command
+        .arg("-vf")
+        .arg(filter)
+        .arg("-r")
+        .arg(args.preview_fps.to_string())
+        .arg("-frames:v")
+        .arg((args.preview_fps * PREVIEW_MAX_SECS).to_string())
+        .args(["-an", "-loop", "0"]);
+    if preview == PreviewFormat::Webp {
+        command.args(["-c:v", "libwebp", "-quality", "70"]);
+    }
+    command
+        .args(thread_args(args))
+        .args(["-loglevel", "error", "-y"])
+        .arg(&output);
+
+    if let Err(e) = run_command(&mut command, deadline, false, &dir.join(FFMPEG_LOG)) {
+        if matches!(e.downcast_ref::<FailureKind>(), Some(FailureKind::Cancelled)) {
+            return Err(e);
+        }
+        warn!("生成动画预览失败，仅保留关键帧: {}: {:#}", video_path.display(), e);
+        let _ = std::fs::remove_file(&output);
+    }
+    Ok(())
+}
+
+// 按文件名顺序列出目录中的输出帧，并依次对应 showinfo 解析出的时间戳
+pub(crate) fn list_frames(dir: &Path, pts: &[f64], pattern: Option<&FramePattern>) -> Result<Vec<FrameEntry>> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .with_context(|| format!("读取目录失败: {:?}", dir))?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| is_keyframe_file(name, pattern))
+        .collect();
+    // 同一模板的文件名只有帧序号不同，先比较长度使不补零的 {index} 也按数值排序
+    names.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+
+    Ok(names
+        .into_iter()
+        .enumerate()
+        .map(|(i, filename)| FrameEntry {
+            filename,
+            pts_seconds: pts.get(i).copied().filter(|pts| pts.is_finite()),
+            frame_index: i + 1,
+            sharpness: None,
+            duplicates: Vec::new(),
+        })
+        .collect())
+}
+
+// 将 keyframe_00001 形式的文件按时间戳重命名为 keyframe_000123.45s，
+// 时间戳补零到固定宽度，按文件名排序即为时间顺序
+fn rename_by_timestamps(dir: &Path, format: OutputFormat, frames: &mut [FrameEntry]) -> Result<()> {
+    for frame in frames {
+        let Some(seconds) = frame.pts_seconds else {
+            continue;
+        };
+        let name = format!("keyframe_{:09.2}s", seconds);
+        let mut filename = format!("{}.{}", name, format.extension());
+        // 两帧时间戳在两位小数下相同时追加序号，避免覆盖且不影响排序
+        let mut duplicate = 1;
+        while dir.join(&filename).exists() {
+            duplicate += 1;
+            filename = format!("{}_{}.{}", name, duplicate, format.extension());
+        }
+        let target = dir.join(&filename);
+        std::fs::rename(dir.join(&frame.filename), &target)
+            .with_context(|| format!("重命名关键帧失败: {:?}", target))?;
+        frame.filename = filename;
+    }
+    Ok(())
+}
+
+// 要提取的百分比位置：--positions 原样使用，--positions-count 取N等分区间的中点
+pub(crate) fn position_percents(args: &ExtractOptions) -> Option<Vec<f64>> {
+    if let Some(positions) = &args.positions {
+        return Some(positions.clone());
+    }
+    args.positions_count.map(|count| {
+        (0..count)
+            .map(|i| (f64::from(i) + 0.5) * 100.0 / f64::from(count))
+            .collect()
+    })
+}
+
+// 按百分比位置逐个快速定位 (-ss 位于 -i 之前) 并各提取一帧，文件名为 pos_<百分比>pct
+// 定位超出视频末尾时FFmpeg不会产生输出，该位置即被跳过
+fn extract_positions(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    percents: &[f64],
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    deadline: Option<Deadline>,
+) -> Result<Vec<FrameEntry>> {
+    let duration = known_duration(video_path, info, args)?;
+    let format = args.format;
+    let source = source_filters(info, args);
+    let mut seen = std::collections::HashSet::new();
+    let mut frames = Vec::new();
+
+    let mut percents = percents.to_vec();
+    percents.sort_by(f64::total_cmp);
+    for percent in percents {
+        let label = percent.round() as u32;
+        if !seen.insert(label) {
+            continue;
+        }
+        let seek = duration * percent / 100.0;
+        let filename = format!("pos_{:03}pct.{}", label, format.extension());
+        let output_file = dir.join(&filename);
+
+        let mut command = Command::new(&args.ffmpeg_path);
+        command
+            .args(hwaccel.args())
+            .args(extra_args(&args.ffmpeg_input_args))
+            .arg("-ss")
+            .arg(format!("{:.3}", seek))
+            .args(source.input_args)
+            .arg("-i")
+            .arg(video_path)
+            .args(["-frames:v", "1"]);
+        if let Some(filter) = frame_filter(&source, args) {
+            command.arg("-vf").arg(filter);
+        }
+        command
+            .args(["-c:v", format.encoder()])
+            .args(format.quality_args(args.quality.for_video(video_path)))
+            .args(thread_args(args))
+            .args(["-loglevel", "error"])
+            .args(extra_args(&args.ffmpeg_output_args))
+            .arg(&output_file);
+        run_command(&mut command, deadline, false, &dir.join(FFMPEG_LOG))?;
+
+        if output_file.exists() {
+            frames.push(FrameEntry {
+                filename,
+                pts_seconds: Some(seek),
+                frame_index: frames.len() + 1,
+                sharpness: None,
+                duplicates: Vec::new(),
+            });
+        }
+    }
+
+    Ok(frames)
+}
@@ -0,0 +1,934 @@
+// 构建和运行FFmpeg命令：抽帧和源画面滤镜、分段并行、按关键帧索引定位、超时与中断
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::api::ExtractOptions;
+use crate::args::{ExtractMode, HwAccel, ShellArgs};
+use crate::discovery::video_stem;
+use crate::error::{FailureKind, SegmentFailed};
+use crate::extract::{extraction_window, is_retryable, list_frames, reset_dir, FFMPEG_LOG};
+use crate::probe::{ffprobe_path, VideoInfo};
+use crate::CANCELLED;
+
+// 未指定 --ffmpeg-threads 时，把CPU核数平均分给线程池中的每个并行任务
+fn default_ffmpeg_threads(cpus: usize, pool_size: usize) -> usize {
+    (cpus / pool_size.max(1)).max(1)
+}
+
+// 每个ffmpeg进程的线程参数；0表示不传 -threads，交给ffmpeg决定
+pub(crate) fn thread_args(args: &ExtractOptions) -> Vec<String> {
+    let threads = args
+        .ffmpeg_threads
+        .unwrap_or_else(|| default_ffmpeg_threads(num_cpus::get(), args.threads));
+    if threads == 0 {
+        Vec::new()
+    } else {
+        vec!["-threads".to_string(), threads.to_string()]
+    }
+}
+
+// --timeout 对应的截止时间，在开始处理视频时确定，该视频的所有FFmpeg调用 (含重试和回退) 共用
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deadline {
+    at: Instant,
+    limit: Duration,
+}
+
+impl Deadline {
+    pub(crate) fn from_args(args: &ExtractOptions) -> Option<Self> {
+        let limit = Duration::from_secs(args.timeout?);
+        Some(Deadline { at: Instant::now() + limit, limit })
+    }
+
+    fn expired(&self) -> bool {
+        Instant::now() >= self.at
+    }
+}
+
+// 等待子进程结束；超过截止时间或收到中断信号时终止进程并返回对应错误
+fn wait_with_timeout(child: &mut Child, deadline: Option<Deadline>) -> Result<ExitStatus> {
+    loop {
+        // FFmpeg与本进程同属一个进程组，Ctrl-C 时通常已自行退出，此时不按普通失败处理
+        if CANCELLED.load(Ordering::SeqCst) {
+            terminate(child);
+            return Err(FailureKind::Cancelled.into());
+        }
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if let Some(deadline) = deadline.filter(Deadline::expired) {
+            terminate(child);
+            return Err(FailureKind::Timeout(deadline.limit).into());
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// 终止子进程：Unix 上先发 SIGTERM 让FFmpeg正常收尾，宽限期后仍未退出再 SIGKILL
+#[cfg(unix)]
+fn terminate(child: &mut Child) {
+    const GRACE: Duration = Duration::from_secs(5);
+    // SAFETY: 仅向由我们启动且尚未回收的子进程发送信号
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let deadline = Instant::now() + GRACE;
+    while Instant::now() < deadline {
+        if let Ok(Some(_)) = child.try_wait() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+// 根据抽帧模式构建 -vf 滤镜
+pub(crate) fn video_filter(args: &ExtractOptions, source: Option<&str>) -> String {
+    let mut filter = select_filter(args);
+    if args.dedup_filter {
+        filter.push_str(",mpdecimate");
+    }
+    if let Some(source) = source {
+        filter.push(',');
+        filter.push_str(source);
+    }
+    // 第二个 select 中的 n 只对已选中的帧计数，从而每 K 帧保留一帧
+    if let Some(every) = args.every.filter(|&k| k > 1) {
+        filter.push_str(&format!(",select=not(mod(n\\,{}))", every));
+    }
+    if let Some(scale) = scale_filter(args) {
+        filter.push(',');
+        filter.push_str(&scale);
+    }
+    filter
+}
+
+// 按 --mode 选帧的滤镜
+pub(crate) fn select_filter(args: &ExtractOptions) -> String {
+    match args.mode {
+        ExtractMode::Keyframe if !args.pict_types.is_empty() => {
+            // 多个类型的条件相加，任一成立即选中
+            let mut names: Vec<&str> = Vec::new();
+            for pict_type in &args.pict_types {
+                if !names.contains(&pict_type.name()) {
+                    names.push(pict_type.name());
+                }
+            }
+            let terms: Vec<String> =
+                names.iter().map(|name| format!("eq(pict_type\\,{})", name)).collect();
+            format!("select={}", terms.join("+"))
+        }
+        ExtractMode::Keyframe => "select=eq(pict_type\\,I)".to_string(), // 提取I帧
+        ExtractMode::Scene => format!("select=gt(scene\\,{})", args.scene_threshold),
+        ExtractMode::Interval => format!("fps=1/{}", args.interval_secs),
+    }
+}
+
+// 按源视频元数据加入的滤镜：HDR 色调映射和画面转正
+pub(crate) struct SourceFilters {
+    // 探测到旋转角度或 --no-autorotate 时关闭FFmpeg的自动旋转
+    pub(crate) input_args: &'static [&'static str],
+    pub(crate) filter: Option<String>,
+}
+
+// HDR 源按 --tonemap 先做色调映射 (SDR 源不受影响)。探测到旋转角度时关闭FFmpeg的自动旋转并显式
+// 加入转正滤镜，结果不依赖FFmpeg版本和硬件解码路径是否应用了自动旋转；无法探测时保留FFmpeg默认的自动旋转
+pub(crate) fn source_filters(info: &VideoInfo, args: &ExtractOptions) -> SourceFilters {
+    let tonemap = info.hdr.and_then(|hdr| args.tonemap.algorithm(hdr)).map(tonemap_filter);
+    let degrees = if args.no_autorotate { Some(0) } else { info.rotation };
+    let rotation = degrees.and_then(rotation_filter).map(str::to_string);
+    let filters: Vec<String> = tonemap.into_iter().chain(rotation).collect();
+    SourceFilters {
+        input_args: if degrees.is_some() { &["-noautorotate"] } else { &[] },
+        filter: (!filters.is_empty()).then(|| filters.join(",")),
+    }
+}
+
+// PQ/HLG 转为线性光后映射到 BT.709 SDR，避免直接截断为8位时画面发灰、褪色
+fn tonemap_filter(algorithm: &str) -> String {
+    format!(
+        "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap={}:desat=0,\
+         zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+        algorithm
+    )
+}
+
+// 顺时针旋转 degrees 度的滤镜 (transpose=1 为顺时针，2 为逆时针)
+fn rotation_filter(degrees: u32) -> Option<&'static str> {
+    match degrees {
+        90 => Some("transpose=1"),
+        180 => Some("hflip,vflip"),
+        270 => Some("transpose=2"),
+        _ => None,
+    }
+}
+
+// 单帧输出的滤镜：先做色调映射和转正再缩放，使 --max-width/--max-height 作用于转正后的画面
+pub(crate) fn frame_filter(source: &SourceFilters, args: &ExtractOptions) -> Option<String> {
+    let filters: Vec<String> = source.filter.clone().into_iter().chain(scale_filter(args)).collect();
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+// 根据 --max-width/--max-height (或 --scale) 构建缩放滤镜，均未指定时不缩放；-2 与 force_divisible_by 保证尺寸为偶数
+fn scale_filter(args: &ExtractOptions) -> Option<String> {
+    let bounds = match args.scale {
+        Some((w, h)) => (Some(w), Some(h)),
+        None => (args.max_width, args.max_height),
+    };
+    match bounds {
+        (None, None) => None,
+        // 限定的一边也取偶数，源尺寸或指定值为奇数时向下取整
+        (Some(w), None) => Some(format!("scale=trunc(min({}\\,iw)/2)*2:-2", w)),
+        (None, Some(h)) => Some(format!("scale=-2:trunc(min({}\\,ih)/2)*2", h)),
+        (Some(w), Some(h)) => Some(format!(
+            "scale=min({}\\,iw):min({}\\,ih):force_original_aspect_ratio=decrease:force_divisible_by=2",
+            w, h
+        )),
+    }
+}
+
+// 调用FFmpeg将关键帧提取到指定目录，需要时间戳时返回每个输出帧的 pts_time。
+// 指定 --segment-parallel 且视频足够长时分段并行提取，--fast-seek 时按关键帧索引逐个定位提取
+pub(crate) fn run_ffmpeg(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    deadline: Option<Deadline>,
+) -> Result<Vec<f64>> {
+    if args.fast_seek {
+        match probe_keyframes(video_path, args) {
+            Ok(index) => {
+                return run_fast_seek(video_path, info, dir, args, hwaccel, &index, deadline)
+            }
+            Err(e) => warn!("读取关键帧索引失败，改用滤镜方式提取: {} ({:#})", video_path.display(), e),
+        }
+    }
+    match segment_ranges(info, args) {
+        Some(ranges) => run_segments(video_path, info, dir, args, hwaccel, &ranges, deadline),
+        None => {
+            let range = (args.start, args.end);
+            run_ffmpeg_range(video_path, info, dir, args, hwaccel, range, deadline)
+        }
+    }
+}
+
+// 分段的时间范围；不分段时为 None
+fn segment_ranges(info: &VideoInfo, args: &ExtractOptions) -> Option<Vec<(f64, f64)>> {
+    let count = args.segment_parallel?;
+    let duration = info.duration?;
+    let start = args.start.unwrap_or(0.0);
+    let window = extraction_window(duration, args);
+    if window < args.segment_min_secs {
+        return None;
+    }
+    // interval 模式的分段点对齐到间隔的整数倍，使各段的抽帧时间与不分段时一致
+    let align = (args.mode == ExtractMode::Interval).then_some(args.interval_secs);
+    Some(split_window(start, start + window, count, align))
+}
+
+// 把 [start, end) 等分为 count 段，align 指定时每段长度向上取整到它的整数倍
+fn split_window(start: f64, end: f64, count: u32, align: Option<f64>) -> Vec<(f64, f64)> {
+    let mut step = (end - start) / f64::from(count);
+    if let Some(align) = align {
+        step = (step / align).ceil() * align;
+    }
+    (0..count)
+        .map(|i| (start + step * f64::from(i), (start + step * f64::from(i + 1)).min(end)))
+        .filter(|(from, to)| to > from)
+        .collect()
+}
+
+// 非 interval 模式下相邻片段向两侧多提取的秒数，保证分段点附近的帧至少被一个片段提取到
+const SEGMENT_OVERLAP_SECS: f64 = 0.1;
+
+// 合并片段时认为是同一帧的时间戳误差 (秒)：-ss 按毫秒取整，各片段换算出的绝对时间略有不同
+const SEGMENT_PTS_TOLERANCE: f64 = 0.002;
+
+// 各片段并行提取到 dir 下的子目录，全部成功后按时间顺序重新编号移入 dir，
+// 并把各片段的FFmpeg日志依次追加到 dir 的日志中。
+// 分段时总是读取时间戳，按 merge_segment_frames 去掉落在相邻片段的帧和重复帧
+fn run_segments(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    ranges: &[(f64, f64)],
+    deadline: Option<Deadline>,
+) -> Result<Vec<f64>> {
+    debug!("分 {} 段并行提取: {}", ranges.len(), video_path.display());
+    // interval 模式的分段点已对齐到间隔，多提取反而会错开 fps 滤镜的抽帧时间
+    let overlap = if args.mode == ExtractMode::Interval { 0.0 } else { SEGMENT_OVERLAP_SECS };
+    let (window_start, window_end) = (ranges[0].0, ranges[ranges.len() - 1].1);
+    let segments: Vec<(PathBuf, Vec<f64>)> = ranges
+        .par_iter()
+        .enumerate()
+        .map(|(i, &(from, to))| {
+            let segment_dir = dir.join(format!("segment_{:03}", i + 1));
+            let seek_from = (from - overlap).max(window_start);
+            let seek_to = (to + overlap).min(window_end);
+            let range = (Some(seek_from), Some(seek_to));
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+                reset_dir(&segment_dir)?;
+                let mut command = ffmpeg_range_command(
+                    video_path,
+                    info,
+                    &segment_dir,
+                    args,
+                    hwaccel,
+                    range,
+                    true,
+                );
+                let log = segment_dir.join(FFMPEG_LOG);
+                let extracted = run_command(&mut command, deadline, true, &log)
+                    .map(|stderr| absolute_pts(&stderr, range.0));
+                match extracted {
+                    Ok(pts) => return Ok((segment_dir, pts)),
+                    Err(e) if attempts <= args.retries && is_retryable(&e) => {
+                        warn!(
+                            "片段 {:.3}-{:.3} 秒第 {} 次尝试失败，重试: {} ({})",
+                            from,
+                            to,
+                            attempts,
+                            video_path.display(),
+                            e
+                        );
+                    }
+                    Err(e) => return Err(e.context(SegmentFailed(from, to))),
+                }
+            }
+        })
+        .collect::<Result<_>>()?;
+
+    let segment_pts: Vec<&[f64]> = segments.iter().map(|(_, pts)| pts.as_slice()).collect();
+    let keep = merge_segment_frames(ranges, &segment_pts);
+    let stem = video_stem(video_path).unwrap_or_default();
+    let stem = stem.to_string_lossy();
+    let ext = args.format.extension();
+    let mut all_pts = Vec::new();
+    let mut index = 0;
+    for ((segment_dir, pts), keep) in segments.iter().zip(keep) {
+        append_log(&segment_dir.join(FFMPEG_LOG), &dir.join(FFMPEG_LOG));
+        let frames = list_frames(segment_dir, pts, args.pattern.as_ref())?;
+        for (k, frame) in frames.into_iter().enumerate() {
+            if !keep.get(k).copied().unwrap_or(true) {
+                continue;
+            }
+            if args.max_frames.is_some_and(|max| index as u64 >= max) {
+                break;
+            }
+            index += 1;
+            let filename = match &args.pattern {
+                Some(pattern) => pattern.file_name(&stem, ext, index),
+                None => format!("keyframe_{:05}.{}", index, ext),
+            };
+            let target = dir.join(filename);
+            std::fs::rename(segment_dir.join(&frame.filename), &target)
+                .with_context(|| format!("移动关键帧失败: {:?}", target))?;
+            all_pts.push(frame.pts_seconds.unwrap_or(f64::NAN));
+        }
+        std::fs::remove_dir_all(segment_dir)
+            .with_context(|| format!("删除片段目录失败: {:?}", segment_dir))?;
+    }
+    Ok(all_pts)
+}
+
+// 决定各片段提取到的帧 (按时间戳顺序) 是否保留：每个片段只保留 [from, to) 内的帧
+// (第一段不限起点，最后一段不限终点)，并跳过与已保留的帧时间戳相同的重复帧。
+// 无法解析时间戳的帧总是保留
+fn merge_segment_frames(ranges: &[(f64, f64)], segments: &[&[f64]]) -> Vec<Vec<bool>> {
+    let last_segment = ranges.len().saturating_sub(1);
+    let mut last_kept: Option<f64> = None;
+    let mut keep = Vec::with_capacity(segments.len());
+    for (i, (&(from, to), pts)) in ranges.iter().zip(segments).enumerate() {
+        let flags = pts
+            .iter()
+            .map(|&pts| {
+                if pts.is_nan() {
+                    return true;
+                }
+                let owned = (i == 0 || pts >= from - SEGMENT_PTS_TOLERANCE)
+                    && (i == last_segment || pts < to);
+                let duplicate = last_kept.is_some_and(|last| pts <= last + SEGMENT_PTS_TOLERANCE);
+                if owned && !duplicate {
+                    last_kept = Some(pts);
+                }
+                owned && !duplicate
+            })
+            .collect();
+        keep.push(flags);
+    }
+    keep
+}
+
+// ffprobe 读取的关键帧索引：提取窗口内关键帧的 pts_time (升序)、窗口内的视频包总数和读取索引的耗时
+struct KeyframeIndex {
+    keyframes: Vec<f64>,
+    packets: usize,
+    probe_time: Duration,
+}
+
+// 只读取包信息 (不解码) 列出视频流的关键帧；--start/--end 通过 -read_intervals 限定读取范围
+fn probe_keyframes(video_path: &Path, args: &ExtractOptions) -> Result<KeyframeIndex> {
+    let started = Instant::now();
+    let start = args.start.unwrap_or(0.0);
+    let end = args.end.unwrap_or(f64::INFINITY);
+    let mut command = Command::new(ffprobe_path(args));
+    command
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "packet=pts_time,flags", "-of", "csv=p=0"]);
+    if args.start.is_some() || args.end.is_some() {
+        let to = args.end.map(|end| format!("{:.3}", end)).unwrap_or_default();
+        command.arg("-read_intervals").arg(format!("{:.3}%{}", start, to));
+    }
+    let output = command.arg(video_path).output().context("执行ffprobe命令失败")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffprobe返回错误状态: {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let mut keyframes = Vec::new();
+    let mut packets = 0;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.trim().split(',');
+        let Some(Ok(pts)) = fields.next().map(str::parse::<f64>) else {
+            continue;
+        };
+        if pts < start || pts >= end {
+            continue;
+        }
+        packets += 1;
+        if fields.next().is_some_and(|flags| flags.starts_with('K')) {
+            keyframes.push(pts);
+        }
+    }
+    if keyframes.is_empty() {
+        anyhow::bail!("ffprobe未列出任何关键帧");
+    }
+    keyframes.sort_by(f64::total_cmp);
+    keyframes.dedup();
+    // 与滤镜方式的 --every 相同，从第一个关键帧起每 K 个取一个
+    if let Some(every) = args.every.filter(|&k| k > 1) {
+        keyframes = keyframes.into_iter().step_by(every as usize).collect();
+    }
+    if let Some(max_frames) = args.max_frames {
+        keyframes.truncate(usize::try_from(max_frames).unwrap_or(usize::MAX));
+    }
+    Ok(KeyframeIndex { keyframes, packets, probe_time: started.elapsed() })
+}
+
+// 每次FFmpeg调用处理的关键帧数，摊薄进程启动和打开文件的开销
+const FAST_SEEK_BATCH: usize = 32;
+
+// 按关键帧索引提取：每个关键帧作为一个输入，在输入端 -ss 定位到该关键帧后只解码一帧，
+// 多个输入在同一次调用中各自映射到一个输出文件
+fn run_fast_seek(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    index: &KeyframeIndex,
+    deadline: Option<Deadline>,
+) -> Result<Vec<f64>> {
+    let started = Instant::now();
+    let format = args.format;
+    let source = source_filters(info, args);
+    let filter = frame_filter(&source, args);
+    let stem = video_stem(video_path).unwrap_or_default();
+    let stem = stem.to_string_lossy();
+    let mut pts = Vec::new();
+
+    for (batch_index, batch) in index.keyframes.chunks(FAST_SEEK_BATCH).enumerate() {
+        let mut command = Command::new(&args.ffmpeg_path);
+        command.args(["-loglevel", "error"]);
+        for &keyframe in batch {
+            command
+                .args(hwaccel.args())
+                .args(extra_args(&args.ffmpeg_input_args))
+                .arg("-ss")
+                .arg(format!("{:.6}", keyframe))
+                .args(source.input_args)
+                .arg("-i")
+                .arg(video_path);
+        }
+        let outputs: Vec<PathBuf> = (0..batch.len())
+            .map(|i| {
+                let number = batch_index * FAST_SEEK_BATCH + i + 1;
+                dir.join(match &args.pattern {
+                    Some(pattern) => pattern.file_name(&stem, format.extension(), number),
+                    None => format!("keyframe_{:05}.{}", number, format.extension()),
+                })
+            })
+            .collect();
+        for (i, output) in outputs.iter().enumerate() {
+            command.arg("-map").arg(format!("{}:v:0", i)).args(["-frames:v", "1"]);
+            if let Some(filter) = &filter {
+                command.arg("-vf").arg(filter);
+            }
+            command
+                .args(["-c:v", format.encoder()])
+                .args(format.quality_args(args.quality.for_video(video_path)))
+                .args(thread_args(args))
+                .args(extra_args(&args.ffmpeg_output_args))
+                .arg(output);
+        }
+        run_command(&mut command, deadline, false, &dir.join(FFMPEG_LOG))?;
+        // 个别关键帧没有解码出画面时不输出文件，时间戳只保留实际写出的帧
+        pts.extend(
+            batch
+                .iter()
+                .zip(&outputs)
+                .filter(|(_, output)| output.is_file())
+                .map(|(&keyframe, _)| keyframe),
+        );
+    }
+
+    debug!(
+        "--fast-seek: {} 个关键帧，只解码 {} 帧 (滤镜方式需解码 {} 帧，约 {:.1} 倍)；读取索引 {:.2} 秒，提取 {:.2} 秒: {}",
+        index.keyframes.len(),
+        index.keyframes.len(),
+        index.packets,
+        index.packets as f64 / index.keyframes.len() as f64,
+        index.probe_time.as_secs_f64(),
+        started.elapsed().as_secs_f64(),
+        video_path.display()
+    );
+    Ok(pts)
+}
+
+// 把片段的日志追加到视频的日志，日志只用于排查问题，失败时忽略
+fn append_log(from: &Path, to: &Path) {
+    let Ok(content) = std::fs::read(from) else {
+        return;
+    };
+    let appended = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(to)
+        .and_then(|mut file| file.write_all(&content));
+    if let Err(e) = appended {
+        debug!("追加FFmpeg日志失败: {:?}: {}", to, e);
+    }
+}
+
+// 对 range (起点, 终点) 运行一次FFmpeg，输出帧和日志写入 dir
+fn run_ffmpeg_range(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    (start, end): (Option<f64>, Option<f64>),
+    deadline: Option<Deadline>,
+) -> Result<Vec<f64>> {
+    let want_pts = args.timestamp_names || args.timestamps || !args.no_manifest;
+    let range = (start, end);
+    let mut command = ffmpeg_range_command(video_path, info, dir, args, hwaccel, range, want_pts);
+
+    let stderr = run_command(&mut command, deadline, want_pts, &dir.join(FFMPEG_LOG))?;
+    Ok(absolute_pts(&stderr, start))
+}
+
+// 从 showinfo 日志解析输出帧的时间戳，输入定位后输出时间戳从0开始，加回起点得到源视频中的绝对时间
+fn absolute_pts(stderr: &str, start: Option<f64>) -> Vec<f64> {
+    let offset = start.unwrap_or(0.0);
+    parse_showinfo_pts(stderr)
+        .into_iter()
+        .map(|pts| pts + offset)
+        .collect()
+}
+
+// 构建 run_ffmpeg_range 的FFmpeg命令；want_pts 时输出 showinfo 日志以读取时间戳
+fn ffmpeg_range_command(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    args: &ExtractOptions,
+    hwaccel: HwAccel,
+    (start, end): (Option<f64>, Option<f64>),
+    want_pts: bool,
+) -> Command {
+    let format = args.format;
+    // 构建FFmpeg命令，路径以OsStr传递以支持非UTF-8文件名
+    let output_pattern = match &args.pattern {
+        Some(pattern) => {
+            let stem = video_stem(video_path).unwrap_or_default();
+            dir.join(pattern.ffmpeg_pattern(&stem.to_string_lossy(), format.extension()))
+        }
+        None => dir.join(format!("keyframe_%05d.{}", format.extension())),
+    };
+
+    // 需要时间戳时在滤镜链末尾加入 showinfo，从其日志中读取每个输出帧的 pts_time
+    let source = source_filters(info, args);
+    let mut filter = video_filter(args, source.filter.as_deref());
+    if want_pts {
+        filter.push_str(",showinfo");
+    }
+
+    let mut command = Command::new(&args.ffmpeg_path);
+    command
+        .args(hwaccel.args())                // 硬件加速
+        .args(extra_args(&args.ffmpeg_input_args));
+    // -ss 放在 -i 之前做输入定位 (快速跳转到最近的关键帧)，结束点换算为输出时长 -t，
+    // 这样无需关心定位后时间戳是否从0开始
+    if let Some(start) = start {
+        command.arg("-ss").arg(format!("{:.3}", start));
+    }
+    command
+        .args(decode_args(args))
+        .args(source.input_args)
+        .arg("-i")
+        .arg(video_path);
+    if let Some(end) = end {
+        let duration = end - start.unwrap_or(0.0);
+        command.arg("-t").arg(format!("{:.3}", duration));
+    }
+    command.arg("-vf").arg(filter);
+    // fps 滤镜本身输出恒定帧率，不需要 vfr
+    if args.mode != ExtractMode::Interval {
+        command.args(["-vsync", "vfr"]);
+    }
+    command
+        .args(["-c:v", format.encoder()])
+        .args(format.quality_args(args.quality.for_video(video_path))); // 质量参数
+    if let Some(max_frames) = args.max_frames {
+        command.arg("-frames:v").arg(max_frames.to_string());
+    }
+    command.args(thread_args(args)); // 每个任务线程数
+    if want_pts {
+        command.args(["-loglevel", "info", "-nostats"]);
+    } else {
+        command.args(["-loglevel", "error"]);
+    }
+    command
+        .args(extra_args(&args.ffmpeg_output_args))
+        .arg(&output_pattern);
+    command
+}
+
+// --idr-only 时在输入端跳过非关键帧的解码
+pub(crate) fn decode_args(args: &ExtractOptions) -> &'static [&'static str] {
+    if args.idr_only {
+        &["-skip_frame", "nokey"]
+    } else {
+        &[]
+    }
+}
+
+// 用户通过 --ffmpeg-*-args 追加的参数
+pub(crate) fn extra_args(extra: &Option<ShellArgs>) -> &[String] {
+    extra.as_ref().map_or(&[], |ShellArgs(args)| args.as_slice())
+}
+
+// 将命令格式化为可直接粘贴到shell中执行的字符串
+fn format_command(command: &Command) -> String {
+    let program = command.get_program().to_string_lossy();
+    let args: Vec<String> = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let mut words = vec![program.into_owned()];
+    words.extend(args);
+    shell_words::join(words)
+}
+
+// 启动FFmpeg并等待结束，检查退出状态；capture_stderr 时返回其完整stderr输出。
+// stderr 总是被捕获并追加写入 log_path，失败时错误信息附上最后几行；
+// 超过 deadline 时终止FFmpeg，已经超过时不再启动
+pub(crate) fn run_command(
+    command: &mut Command,
+    deadline: Option<Deadline>,
+    capture_stderr: bool,
+    log_path: &Path,
+) -> Result<String> {
+    let command_line = format_command(command);
+    debug!("执行: {}", command_line);
+    command.stderr(Stdio::piped());
+    if CANCELLED.load(Ordering::SeqCst) {
+        return Err(FailureKind::Cancelled.into());
+    }
+    if let Some(deadline) = deadline.filter(Deadline::expired) {
+        return Err(FailureKind::Timeout(deadline.limit).into());
+    }
+    // 日志写不了不影响提取，只是失败时少一份完整输出
+    let mut log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .map_err(|e| debug!("无法写入FFmpeg日志 {}: {}", log_path.display(), e))
+        .ok();
+    if let Some(log) = &mut log {
+        let _ = writeln!(log, "# {}", command_line);
+    }
+    let mut child = command.spawn().context(FailureKind::Spawn)?;
+
+    // 在单独线程中读取stderr，避免管道写满导致FFmpeg阻塞
+    let reader = child
+        .stderr
+        .take()
+        .map(|stderr| std::thread::spawn(move || read_stderr(stderr, capture_stderr, log)));
+    let status = wait_with_timeout(&mut child, deadline);
+    let (stderr, tail) = reader
+        .map(|handle| handle.join().unwrap_or_default())
+        .unwrap_or_default();
+
+    let status = status?;
+    if !status.success() {
+        return Err(FailureKind::FfmpegStatus(status, tail.into()).into());
+    }
+
+    Ok(stderr)
+}
+
+// 失败时在错误信息中保留的 stderr 行数
+const STDERR_TAIL_LINES: usize = 10;
+
+// 逐行读取stderr并原样写入日志文件：内存中只保留最后 STDERR_TAIL_LINES 个非空行，
+// 避免输出很多的FFmpeg占用大量内存；keep_all 时 (需要解析 showinfo 日志) 另外返回完整输出
+fn read_stderr(
+    stderr: impl Read,
+    keep_all: bool,
+    mut log: Option<std::fs::File>,
+) -> (String, VecDeque<String>) {
+    let mut reader = std::io::BufReader::new(stderr);
+    let mut all = String::new();
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if let Some(file) = &mut log {
+            let _ = file.write_all(&buf);
+        }
+        let line = String::from_utf8_lossy(&buf);
+        if keep_all {
+            all.push_str(&line);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line.to_string());
+    }
+    (all, tail)
+}
+
+// 从 showinfo 滤镜日志中按顺序解析每个输出帧的 pts_time (秒)；
+// 无法解析的时间戳 (如 NOPTS、nan) 记为 NaN 占位，保证与输出帧一一对应
+fn parse_showinfo_pts(log: &str) -> Vec<f64> {
+    log.lines()
+        .filter(|line| line.contains("Parsed_showinfo"))
+        .filter_map(|line| {
+            let rest = &line[line.find("pts_time:")? + "pts_time:".len()..];
+            let pts = rest.split_whitespace().next().and_then(|pts| pts.parse().ok());
+            Some(pts.filter(|pts: &f64| pts.is_finite()).unwrap_or(f64::NAN))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{output_dir_for, OutputPlan};
+    use std::ffi::OsStr;
+    use crate::api::test_args as args;
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_name_is_passed_through() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let video = Path::new("/videos").join(OsStr::from_bytes(b"caf\xe9.mp4"));
+        let args = args(&[]);
+
+        let output_dir = output_dir_for(&video, &args, &OutputPlan::default()).unwrap();
+        assert_eq!(output_dir.file_name().unwrap().as_bytes(), b"caf\xe9");
+        assert_eq!(output_dir.parent(), Some(Path::new("/out")));
+
+        let info = VideoInfo::default();
+        let range = (None, None);
+        let command =
+            ffmpeg_range_command(&video, &info, &output_dir, &args, HwAccel::None, range, false);
+        let command_args: Vec<&OsStr> = command.get_args().collect();
+        let input = command_args.iter().position(|&arg| arg == "-i").unwrap();
+        assert_eq!(command_args[input + 1].as_bytes(), video.as_os_str().as_bytes());
+    }
+
+    #[test]
+    fn keyframe_filter_without_scale() {
+        assert_eq!(video_filter(&args(&[]), None), "select=eq(pict_type\\,I)");
+    }
+
+    #[test]
+    fn keyframe_filter_with_scale() {
+        assert_eq!(
+            video_filter(&args(&["--max-width", "640"]), None),
+            "select=eq(pict_type\\,I),scale=trunc(min(640\\,iw)/2)*2:-2"
+        );
+        assert_eq!(
+            video_filter(&args(&["--max-height", "360"]), None),
+            "select=eq(pict_type\\,I),scale=-2:trunc(min(360\\,ih)/2)*2"
+        );
+        assert_eq!(
+            video_filter(&args(&["--max-width", "640", "--max-height", "360"]), None),
+            "select=eq(pict_type\\,I),scale=min(640\\,iw):min(360\\,ih):\
+             force_original_aspect_ratio=decrease:force_divisible_by=2"
+        );
+    }
+
+    #[test]
+    fn interval_filter_with_scale() {
+        let args = args(&["--mode", "interval", "--interval-secs", "5", "--max-width", "640"]);
+        assert_eq!(video_filter(&args, None), "fps=1/5,scale=trunc(min(640\\,iw)/2)*2:-2");
+    }
+
+    #[test]
+    fn scale_filter_rounds_odd_bounds_to_even() {
+        assert_eq!(
+            scale_filter(&args(&["--max-width", "641"])).unwrap(),
+            "scale=trunc(min(641\\,iw)/2)*2:-2"
+        );
+        assert_eq!(
+            scale_filter(&args(&["--max-height", "361"])).unwrap(),
+            "scale=-2:trunc(min(361\\,ih)/2)*2"
+        );
+        let both = scale_filter(&args(&["--scale", "641x361"])).unwrap();
+        assert!(both.ends_with("force_divisible_by=2"));
+        assert_eq!(scale_filter(&args(&[])), None);
+    }
+
+    #[test]
+    fn ffmpeg_threads_are_split_across_the_pool() {
+        assert_eq!(default_ffmpeg_threads(16, 4), 4);
+        assert_eq!(default_ffmpeg_threads(8, 3), 2);
+        assert_eq!(default_ffmpeg_threads(8, 1), 8);
+        // 并行任务多于核心时每个ffmpeg至少一个线程
+        assert_eq!(default_ffmpeg_threads(4, 16), 1);
+        assert_eq!(default_ffmpeg_threads(4, 0), 4);
+    }
+
+    #[test]
+    fn ffmpeg_threads_zero_leaves_it_to_ffmpeg() {
+        assert!(thread_args(&args(&["--ffmpeg-threads", "0"])).is_empty());
+        assert_eq!(thread_args(&args(&["--ffmpeg-threads", "3"])), ["-threads", "3"]);
+        let expected = default_ffmpeg_threads(num_cpus::get(), 2).to_string();
+        assert_eq!(thread_args(&args(&["--threads", "2"])), ["-threads", expected.as_str()]);
+    }
+
+    #[test]
+    fn rotation_filters() {
+        assert_eq!(rotation_filter(90), Some("transpose=1"));
+        assert_eq!(rotation_filter(180), Some("hflip,vflip"));
+        assert_eq!(rotation_filter(270), Some("transpose=2"));
+        assert_eq!(rotation_filter(0), None);
+    }
+
+    #[test]
+    fn no_autorotate_skips_the_rotation_filter() {
+        let info = VideoInfo { rotation: Some(90), ..VideoInfo::default() };
+
+        let rotated = source_filters(&info, &args(&[]));
+        assert_eq!(rotated.filter.as_deref(), Some("transpose=1"));
+        assert_eq!(rotated.input_args, ["-noautorotate"]);
+
+        // 不转正时仍要关闭FFmpeg默认的自动旋转，才能得到编码时的原始方向
+        let raw = source_filters(&info, &args(&["--no-autorotate"]));
+        assert_eq!(raw.filter, None);
+        assert_eq!(raw.input_args, ["-noautorotate"]);
+        assert_eq!(
+            video_filter(&args(&["--no-autorotate"]), raw.filter.as_deref()),
+            "select=eq(pict_type\\,I)"
+        );
+    }
+
+    #[test]
+    fn showinfo_pts_keep_one_entry_per_frame() {
+        let stderr = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'in.mp4':
+  Duration: 00:00:10.00, start: -0.033333, bitrate: 1205 kb/s
+[Parsed_showinfo_1 @ 0x5581c0] config in time_base: 1/15360, frame_rate: 30/1
+[Parsed_showinfo_1 @ 0x5581c0] config out time_base: 0/0, frame_rate: 0/0
+[Parsed_showinfo_1 @ 0x5581c0] n:   0 pts:   -512 pts_time:-0.0333333 duration:512 fmt:yuv420p
+[Parsed_showinfo_1 @ 0x5581c0]   side data - display matrix: rotation of -90.00 degrees
+[Parsed_showinfo_1 @ 0x5581c0] color_range:tv color_space:bt709 color_primaries:bt709
+[mjpeg @ 0x5581d0] pts_time:99 is not a showinfo line
+[Parsed_showinfo_1 @ 0x5581c0] n:   1 pts:NOPTS pts_time:nan duration:512 fmt:yuv420p
+frame=    2 fps=0.0 q=2.0 size=N/A time=00:00:02.00 bitrate=N/A speed=4.1x
+[Parsed_showinfo_1 @ 0x5581c0] n:   2 pts:  76800 pts_time:5       duration:512 fmt:yuv420p
+[Parsed_showinfo_1 @ 0x5581c0] n:   3 pts:NOPTS pts_time:NOPTS duration:512 fmt:yuv420p
+";
+        let pts = parse_showinfo_pts(stderr);
+        assert_eq!(pts.len(), 4);
+        assert_eq!(pts[0], -0.0333333);
+        assert!(pts[1].is_nan());
+        assert_eq!(pts[2], 5.0);
+        assert!(pts[3].is_nan());
+        assert!(parse_showinfo_pts("").is_empty());
+    }
+
+    #[test]
+    fn window_is_split_into_contiguous_segments() {
+        assert_eq!(split_window(0.0, 90.0, 3, None), [(0.0, 30.0), (30.0, 60.0), (60.0, 90.0)]);
+        // 对齐到间隔时最后一段截断到窗口终点，多余的空段被丢弃
+        assert_eq!(
+            split_window(10.0, 100.0, 4, Some(25.0)),
+            [(10.0, 35.0), (35.0, 60.0), (60.0, 85.0), (85.0, 100.0)]
+        );
+        assert_eq!(split_window(0.0, 10.0, 4, Some(5.0)), [(0.0, 5.0), (5.0, 10.0)]);
+        let segments = split_window(1.5, 7.25, 3, None);
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+        assert_eq!(segments.last().unwrap().1, 7.25);
+    }
+
+    #[test]
+    fn segment_merge_keeps_boundary_frames_exactly_once() {
+        let ranges = [(0.0, 10.0), (10.0, 20.0), (20.0, 30.0)];
+        // 相邻片段都提取到了分段点附近的帧，且各自换算出的时间戳有毫秒级误差
+        let first = [0.0, 5.0, 9.95, 10.0004];
+        let second = [9.95, 9.9996, 15.0, 20.0];
+        let third = [19.9995, 25.0, 30.0];
+        let keep = merge_segment_frames(&ranges, &[&first, &second, &third]);
+        assert_eq!(keep[0], [true, true, true, false]);
+        assert_eq!(keep[1], [false, true, true, false]);
+        assert_eq!(keep[2], [true, true, true]);
+
+        // 分段点上的帧只被一个片段提取到时也要保留
+        let keep = merge_segment_frames(&ranges[..2], &[&[0.0, 5.0], &[10.0, 15.0]]);
+        assert_eq!(keep, [[true, true], [true, true]]);
+
+        // 无法解析的时间戳不参与去重
+        let keep = merge_segment_frames(&ranges[..2], &[&[f64::NAN, 9.0], &[9.0, f64::NAN]]);
+        assert_eq!(keep, [[true, true], [false, true]]);
+    }
+}
@@ -0,0 +1,198 @@
+// 预检和按时长、分辨率、大小、修改时间等条件筛选视频
+
+use anyhow::Result;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+use tracing::debug;
+
+use crate::api::ExtractOptions;
+use crate::args::OnUnknown;
+use crate::probe::{ffprobe_path, MediaCache, VideoInfo};
+
+// 预检时被 ffprobe 判定为无效的文件
+pub(crate) struct InvalidVideo {
+    pub(crate) path: PathBuf,
+    pub(crate) reason: String,
+}
+
+// 未满足 --min-duration 等筛选条件而跳过的文件
+pub(crate) struct FilteredVideo {
+    pub(crate) path: PathBuf,
+    pub(crate) reason: String,
+}
+
+// 用 ffprobe 并行检查每个候选文件，返回 (有效文件, 无效文件)
+pub(crate) fn validate_videos(paths: Vec<PathBuf>, ffprobe: &Path) -> (Vec<PathBuf>, Vec<InvalidVideo>) {
+    let checked: Vec<(PathBuf, Result<(), String>)> = paths
+        .into_par_iter()
+        .map(|path| {
+            let result = validate_video(ffprobe, &path);
+            (path, result)
+        })
+        .collect();
+
+    let mut valid = Vec::new();
+    let mut invalid = Vec::new();
+    for (path, result) in checked {
+        match result {
+            Ok(()) => valid.push(path),
+            Err(reason) => invalid.push(InvalidVideo { path, reason }),
+        }
+    }
+    (valid, invalid)
+}
+
+// 是否指定了需要 ffprobe 元数据的筛选条件
+pub(crate) fn needs_probe_filter(args: &ExtractOptions) -> bool {
+    args.min_duration.is_some() || args.max_duration.is_some() || args.min_height.is_some()
+}
+
+// 剔除修改时间在 --min-age 秒以内的文件。无法获取修改时间时保留文件；
+// 修改时间晚于当前时间 (时钟偏差) 时视为刚修改
+pub(crate) fn filter_recent(
+    paths: Vec<PathBuf>,
+    args: &ExtractOptions,
+    cache: &MediaCache,
+) -> (Vec<PathBuf>, Vec<FilteredVideo>) {
+    let Some(min_age) = args.min_age else {
+        return (paths, Vec::new());
+    };
+    let now = SystemTime::now();
+    let mut kept = Vec::new();
+    let mut filtered = Vec::new();
+    for path in paths {
+        let Some(modified) = cache.file_stat(&path).and_then(|stat| stat.modified) else {
+            debug!("无法获取修改时间，不按 --min-age 跳过: {}", path.display());
+            kept.push(path);
+            continue;
+        };
+        let age = now.duration_since(modified).unwrap_or_default().as_secs();
+        if age < min_age {
+            let reason = format!("跳过 (修改时间太近): {} 秒前修改，可能仍在写入", age);
+            filtered.push(FilteredVideo { path, reason });
+        } else {
+            kept.push(path);
+        }
+    }
+    (kept, filtered)
+}
+
+// 按时长、高度和文件大小并行筛选，返回 (保留的文件, 被筛掉的文件)；未指定任何条件时原样返回
+pub(crate) fn filter_by_metadata(
+    paths: Vec<PathBuf>,
+    args: &ExtractOptions,
+    cache: &MediaCache,
+) -> (Vec<PathBuf>, Vec<FilteredVideo>) {
+    if !needs_probe_filter(args) && args.min_size.is_none() && args.max_size.is_none() {
+        return (paths, Vec::new());
+    }
+    let ffprobe = ffprobe_path(args);
+    let checked: Vec<(PathBuf, Option<String>)> = paths
+        .into_par_iter()
+        .map(|path| {
+            let rejection = metadata_rejection(&path, &ffprobe, args, cache);
+            (path, rejection)
+        })
+        .collect();
+
+    let mut kept = Vec::new();
+    let mut filtered = Vec::new();
+    for (path, rejection) in checked {
+        match rejection {
+            None => kept.push(path),
+            Some(reason) => filtered.push(FilteredVideo { path, reason }),
+        }
+    }
+    (kept, filtered)
+}
+
+// 文件不满足筛选条件的原因；满足时返回 None
+fn metadata_rejection(
+    path: &Path,
+    ffprobe: &Path,
+    args: &ExtractOptions,
+    cache: &MediaCache,
+) -> Option<String> {
+    if args.min_size.is_some() || args.max_size.is_some() {
+        let Some(size) = cache.file_stat(path).map(|stat| stat.size) else {
+            return Some("无法获取文件大小".to_string());
+        };
+        if let Some(reason) = check_size(size, args) {
+            return Some(reason);
+        }
+    }
+    if !needs_probe_filter(args) {
+        return None;
+    }
+    match cache.video_info(ffprobe, path) {
+        Ok(info) => check_media(&info, args),
+        Err(_) if args.on_unknown == OnUnknown::Include => None,
+        Err(e) => Some(format!("无法获取时长和分辨率: {:#}", e)),
+    }
+}
+
+fn check_size(size: u64, args: &ExtractOptions) -> Option<String> {
+    let mb = |bytes: u64| bytes as f64 / (1 << 20) as f64;
+    if let Some(min) = args.min_size.filter(|&min| size < min) {
+        return Some(format!("大小 {:.1} MiB，小于 {:.1} MiB", mb(size), mb(min)));
+    }
+    if let Some(max) = args.max_size.filter(|&max| size > max) {
+        return Some(format!("大小 {:.1} MiB，大于 {:.1} MiB", mb(size), mb(max)));
+    }
+    None
+}
+
+// 检查时长和画面高度，ffprobe 未能确定的字段按 --on-unknown 处理
+fn check_media(info: &VideoInfo, args: &ExtractOptions) -> Option<String> {
+    let unknown = |what: &str| {
+        (args.on_unknown == OnUnknown::Exclude).then(|| format!("ffprobe未能确定{}", what))
+    };
+    if args.min_duration.is_some() || args.max_duration.is_some() {
+        match info.duration {
+            None => {
+                if let Some(reason) = unknown("视频时长") {
+                    return Some(reason);
+                }
+            }
+            Some(duration) => {
+                if let Some(min) = args.min_duration.filter(|&min| duration < min) {
+                    return Some(format!("时长 {:.1} 秒，短于 {} 秒", duration, min));
+                }
+                if let Some(max) = args.max_duration.filter(|&max| duration > max) {
+                    return Some(format!("时长 {:.1} 秒，长于 {} 秒", duration, max));
+                }
+            }
+        }
+    }
+    if let Some(min) = args.min_height {
+        match info.height {
+            None => return unknown("画面高度"),
+            Some(height) if height < min => {
+                return Some(format!("高度 {} 像素，低于 {} 像素", height, min));
+            }
+            Some(_) => {}
+        }
+    }
+    None
+}
+
+// 文件可被 ffprobe 打开且至少包含一条视频流时视为有效
+fn validate_video(ffprobe: &Path, video_path: &Path) -> Result<(), String> {
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-show_entries", "stream=codec_type"])
+        .args(["-of", "csv=p=0"])
+        .arg(video_path)
+        .output()
+        .map_err(|e| format!("执行ffprobe命令失败: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffprobe无法读取: {}", stderr.trim()));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !stdout.lines().any(|line| line.trim() == "video") {
+        return Err("没有视频流".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use std::sync::atomic::AtomicBool;
+
+mod api;
+mod args;
+mod batch;
+mod cli;
+mod config;
+mod discovery;
+mod error;
+mod extract;
+mod ffmpeg;
+mod filter;
+mod output;
+mod postprocess;
+mod probe;
+mod report;
+mod state;
+mod watch;
+
+pub use api::{
+    discover_videos, extract_keyframes, run_batch, Event, ExtractOptions, ExtractionResult,
+    VidError,
+};
+pub use cli::run_cli;
+
+// 收到 Ctrl-C 后置位：不再开始新的视频，正在运行的FFmpeg被终止
+pub(crate) static CANCELLED: AtomicBool = AtomicBool::new(false);
@@ -0,0 +1,610 @@
+// 输出目录：命名和冲突处理、完成标记、关键帧文件的移动和统计、归档，以及成功后对源视频的处理
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+use crate::api::ExtractOptions;
+use crate::args::{
+    ArchiveCompression, ArchiveFormat, FramePattern, OnCollision, OnSuccess, OutputFormat,
+};
+use crate::discovery::{input_roots, is_url, root_for, video_stem};
+use crate::filter::FilteredVideo;
+use crate::postprocess::CONTACT_SHEET;
+
+// 输出目录已存在时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExistingOutput {
+    /// 视为已完成，跳过
+    Skip,
+    /// 仅替换其中的关键帧文件
+    Overwrite,
+    /// 删除整个目录后重新提取
+    Force,
+}
+
+impl ExistingOutput {
+    pub(crate) fn from_args(args: &ExtractOptions) -> Self {
+        if args.force {
+            ExistingOutput::Force
+        } else if args.overwrite {
+            ExistingOutput::Overwrite
+        } else {
+            ExistingOutput::Skip
+        }
+    }
+}
+
+// 输出目录的分配结果，由 resolve_collisions 生成，处理视频时据此确定输出目录
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OutputPlan {
+    /// 因输出目录冲突而改名的视频 -> 改名后的输出目录
+    renamed: HashMap<PathBuf, PathBuf>,
+    /// 已分配的输出目录 -> 使用它的视频，监视模式下据此检查新视频与之前的视频是否冲突
+    assigned: HashMap<PathBuf, PathBuf>,
+}
+
+// 计算视频的输出目录：因冲突改名的视频使用 plan 中记录的目录，其余同 default_output_dir
+pub(crate) fn output_dir_for(video_path: &Path, args: &ExtractOptions, plan: &OutputPlan) -> Result<PathBuf> {
+    match plan.renamed.get(video_path) {
+        Some(renamed) => Ok(renamed.clone()),
+        None => default_output_dir(video_path, args),
+    }
+}
+
+// 视频未改名时的输出目录 (保留原始字节，不要求UTF-8)
+// 默认按相对于所属输入目录的路径 (去掉扩展名) 组织，有多个输入目录时再加一层输入目录名，
+// --flatten 或使用路径列表时仅使用文件名
+fn default_output_dir(video_path: &Path, args: &ExtractOptions) -> Result<PathBuf> {
+    let stem = video_stem(video_path).context("无效的文件名")?;
+    // 使用路径列表时没有可参照的输入目录，与 --flatten 相同
+    if args.flatten || input_roots(args).is_empty() {
+        return Ok(args.output.join(stem));
+    }
+    let relative = match root_for(video_path, args) {
+        Some((root, relative)) => {
+            let parent = relative.parent().map(Path::to_path_buf).unwrap_or_default();
+            match root_namespace(root, args) {
+                Some(namespace) => Path::new(&namespace).join(parent),
+                None => parent,
+            }
+        }
+        None => {
+            warn!("{} 不在输入目录之下，直接输出到输出目录根部", video_path.display());
+            PathBuf::new()
+        }
+    };
+    Ok(args.output.join(relative).join(stem))
+}
+
+// 多个输入目录时各自输出到以目录名命名的子目录，避免不同输入下的同名子树互相冲突；
+// 目录名相同时依次加 _2、_3 后缀，无法取得目录名 (如 /) 时使用 input<序号>。
+// 只有一个输入目录时，以及直接指定的视频文件，返回 None
+fn root_namespace(root: &Path, args: &ExtractOptions) -> Option<OsString> {
+    let roots: Vec<&PathBuf> = input_roots(args).iter().filter(|r| !r.is_file()).collect();
+    if roots.len() < 2 || root.is_file() {
+        return None;
+    }
+    let base_name = |index: usize, root: &Path| {
+        std::fs::canonicalize(root)
+            .ok()
+            .and_then(|p| p.file_name().map(ToOwned::to_owned))
+            .unwrap_or_else(|| OsString::from(format!("input{}", index + 1)))
+    };
+    let index = roots.iter().position(|r| *r == root)?;
+    let name = base_name(index, root);
+    let same_before = (0..index).filter(|&i| base_name(i, roots[i]) == name).count();
+    if same_before == 0 {
+        return Some(name);
+    }
+    let mut name = name;
+    name.push(format!("_{}", same_before + 1));
+    Some(name)
+}
+
+// 找出映射到同一输出目录的视频 (如 --flatten 时不同目录下的同名文件，或同目录下的 a.mp4 与 a.mkv)，
+// 按 --on-collision 处理：suffix 为除路径排序最前者之外的视频记录改名后的目录，
+// skip 返回被跳过的视频，error 直接报错。plan 中已分配的输出目录 (监视模式下为首次扫描和之前各批)
+// 优先归原来的视频；改名的目录和保留的视频随后也记入 plan
+pub(crate) fn resolve_collisions(
+    video_paths: Vec<PathBuf>,
+    args: &ExtractOptions,
+    plan: &mut OutputPlan,
+) -> Result<(Vec<PathBuf>, Vec<FilteredVideo>)> {
+    let mut groups: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for path in &video_paths {
+        groups.entry(output_dir_for(path, args, plan)?).or_default().push(path.clone());
+    }
+
+    let mut skipped = Vec::new();
+    for (output_dir, mut sources) in groups {
+        sources.sort();
+        match plan.assigned.get(&output_dir) {
+            Some(owner) if !sources.contains(owner) => sources.insert(0, owner.clone()),
+            Some(owner) => {
+                let index = sources.iter().position(|s| s == owner).unwrap_or(0);
+                sources[..=index].rotate_right(1);
+            }
+            None => {}
+        }
+        if sources.len() < 2 {
+            continue;
+        }
+        let names: Vec<String> = sources.iter().map(|p| p.display().to_string()).collect();
+        match args.on_collision {
+            OnCollision::Error => anyhow::bail!(
+                "以下视频的输出目录均为 {}: {}",
+                output_dir.display(),
+                names.join(", ")
+            ),
+            OnCollision::Skip => {
+                warn!("输出目录冲突 {}，只处理 {}", output_dir.display(), names[0]);
+                for source in sources.drain(1..) {
+                    skipped.push(FilteredVideo {
+                        path: source,
+                        reason: format!("输出目录与 {} 冲突", names[0]),
+                    });
+                }
+            }
+            OnCollision::Suffix => {
+                for source in sources.drain(1..) {
+                    let mut name = output_dir.file_name().unwrap_or_default().to_os_string();
+                    name.push(format!("__{:06x}", path_hash(&source) & 0xff_ffff));
+                    let renamed = output_dir.with_file_name(name);
+                    warn!("输出目录冲突，{} 改为输出到 {}", source.display(), renamed.display());
+                    plan.renamed.insert(source, renamed);
+                }
+            }
+        }
+    }
+
+    let skipped_paths: Vec<&PathBuf> = skipped.iter().map(|s| &s.path).collect();
+    let kept: Vec<PathBuf> = video_paths
+        .iter()
+        .filter(|path| !skipped_paths.contains(path))
+        .cloned()
+        .collect();
+    for path in &kept {
+        let output_dir = output_dir_for(path, args, plan)?;
+        plan.assigned.entry(output_dir).or_insert_with(|| path.clone());
+    }
+    Ok((kept, skipped))
+}
+
+// 源路径的 FNV-1a 哈希，跨运行稳定，用于生成不冲突的输出目录名
+fn path_hash(path: &Path) -> u64 {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+// 视频相对于输入目录的路径。直接去前缀失败时 (如监视模式得到的绝对路径、经符号链接到达的目录)
+// 改为比较两者所在目录的规范化路径；文件本身是符号链接时不解析，以保留其在输入目录中的名字
+pub(crate) fn relative_to_input(path: &Path, input: &Path) -> Option<PathBuf> {
+    if let Ok(relative) = path.strip_prefix(input) {
+        return Some(relative.to_path_buf());
+    }
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let parent = std::fs::canonicalize(parent).ok()?;
+    let input = std::fs::canonicalize(input).ok()?;
+    let relative = parent.strip_prefix(&input).ok()?;
+    Some(relative.join(path.file_name()?))
+}
+
+// 提取过程中使用的临时目录：<stem>.<源路径哈希>.tmp-<pid>，成功后再重命名为最终目录；
+// 含源路径哈希，即使两个视频对应同一输出目录，并发提取时也不会共用临时目录
+pub(crate) fn temp_dir_for(output_dir: &Path, video_path: &Path) -> PathBuf {
+    let mut name = output_dir.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{:06x}.tmp-{}",
+        path_hash(video_path) & 0xff_ffff,
+        std::process::id()
+    ));
+    output_dir.with_file_name(name)
+}
+
+// 提取完成标记，所有输出移入输出目录后才写入；有输出但没有标记的目录视为上次被中断，重新提取
+const DONE_MARKER: &str = ".done";
+
+pub(crate) fn is_complete(dir: &Path) -> bool {
+    dir.join(DONE_MARKER).is_file()
+}
+
+// 先写临时文件再重命名，中途退出时不会留下不完整的标记
+pub(crate) fn write_done_marker(dir: &Path, video_path: &Path, frame_count: usize) -> Result<()> {
+    let marker = serde_json::json!({
+        "source": video_path.to_string_lossy(),
+        "frame_count": frame_count,
+    });
+    let path = dir.join(DONE_MARKER);
+    let temp_path = dir.join(format!("{}.tmp", DONE_MARKER));
+    std::fs::write(&temp_path, marker.to_string())
+        .with_context(|| format!("写入完成标记失败: {:?}", temp_path))?;
+    std::fs::rename(&temp_path, &path).with_context(|| format!("写入完成标记失败: {:?}", path))
+}
+
+pub(crate) fn remove_done_marker(dir: &Path) -> Result<()> {
+    match std::fs::remove_file(dir.join(DONE_MARKER)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("删除完成标记失败: {:?}", dir))
+        }
+        _ => Ok(()),
+    }
+}
+
+// 输出目录中是否已有提取结果 (关键帧、清单或 --sheet-only 的总览)；只有空目录或其他文件时视为没有输出
+pub(crate) fn has_output(dir: &Path, pattern: Option<&FramePattern>) -> bool {
+    dir.join("manifest.json").is_file()
+        || dir.join(CONTACT_SHEET).is_file()
+        || count_keyframes(dir, pattern).is_ok_and(|count| count > 0)
+}
+
+// 创建目录；并发创建导致的 AlreadyExists 不算错误
+pub(crate) fn ensure_dir(dir: &Path) -> std::io::Result<()> {
+    match std::fs::create_dir_all(dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && dir.is_dir() => Ok(()),
+        result => result,
+    }
+}
+
+// 判断目录名是否为临时目录 (以 .tmp-<pid> 结尾)
+fn is_temp_dir_name(name: &std::ffi::OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.rsplit_once(".tmp-")
+        .is_some_and(|(_, pid)| !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit()))
+}
+
+// 删除输出目录树中先前运行中断后遗留的临时目录和未写完的归档 (.partial)
+pub(crate) fn remove_stale_temp_dirs(output_root: &Path) -> Result<()> {
+    if !output_root.is_dir() {
+        return Ok(());
+    }
+    let mut walker = WalkDir::new(output_root).min_depth(1).into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = entry?;
+        if entry.file_type().is_dir() && is_temp_dir_name(entry.file_name()) {
+            walker.skip_current_dir();
+            let path = entry.path();
+            info!("清理遗留临时目录: {}", path.display());
+            std::fs::remove_dir_all(path)
+                .with_context(|| format!("删除临时目录失败: {:?}", path))?;
+        } else if entry.file_name().to_string_lossy().ends_with(ARCHIVE_PARTIAL_SUFFIX) {
+            let path = entry.path();
+            info!("清理未写完的归档: {}", path.display());
+            std::fs::remove_file(path).with_context(|| format!("删除文件失败: {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+// 将临时目录中的关键帧移入已存在的输出目录；同名文件先删除，不依赖 rename 在各平台上能否覆盖
+pub(crate) fn move_keyframes(from: &Path, to: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(from).with_context(|| format!("读取目录失败: {:?}", from))? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if target.is_file() {
+            std::fs::remove_file(&target)
+                .with_context(|| format!("删除已有文件失败: {:?}", target))?;
+        }
+        std::fs::rename(entry.path(), &target)
+            .with_context(|| format!("移动关键帧失败: {:?}", target))?;
+    }
+    std::fs::remove_dir(from).with_context(|| format!("删除临时目录失败: {:?}", from))
+}
+
+// 判断文件名是否符合关键帧输出命名
+// (keyframe_<数字>、keyframe_<秒数>s、pos_<数字>pct 或 --pattern 模板，扩展名为任一输出格式)
+pub(crate) fn is_keyframe_file(name: &str, pattern: Option<&FramePattern>) -> bool {
+    if pattern.is_some_and(|pattern| pattern.matches(name)) {
+        return true;
+    }
+    let Some((stem, ext)) = name.rsplit_once('.') else {
+        return false;
+    };
+    let known_ext = OutputFormat::value_variants()
+        .iter()
+        .any(|format| format.extension() == ext);
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let is_seconds = |s: &str| {
+        s.strip_suffix('s')
+            .and_then(|secs| secs.split_once('.'))
+            .is_some_and(|(int, frac)| is_digits(int) && is_digits(frac))
+    };
+    let label = stem
+        .strip_prefix("keyframe_")
+        .map(|rest| rest.rsplit('_').next().unwrap_or(rest))
+        .map(|label| is_digits(label) || is_seconds(label));
+    let position = stem
+        .strip_prefix("pos_")
+        .and_then(|rest| rest.strip_suffix("pct"))
+        .map(is_digits);
+    known_ext && (label == Some(true) || position == Some(true))
+}
+
+// 统计目录中已提取的关键帧数量
+pub(crate) fn count_keyframes(dir: &Path, pattern: Option<&FramePattern>) -> Result<usize> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir).with_context(|| format!("读取目录失败: {:?}", dir))? {
+        if entry?.file_name().to_str().is_some_and(|name| is_keyframe_file(name, pattern)) {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// 删除目录中已提取的关键帧，保留其他文件
+pub(crate) fn remove_keyframes(dir: &Path, pattern: Option<&FramePattern>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("读取目录失败: {:?}", dir))? {
+        let path = entry?.path();
+        let is_keyframe = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| is_keyframe_file(name, pattern));
+        if is_keyframe && path.is_file() {
+            std::fs::remove_file(&path).with_context(|| format!("删除旧关键帧失败: {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+// 本次提取成功后按 --on-success 移动或删除源视频，返回记入报告的处理结果；
+// written 为输出中实际存在的帧文件数，为0时绝不处理源视频。处理失败只警告，已提取的帧不受影响
+pub(crate) fn apply_on_success(video_path: &Path, written: usize, args: &ExtractOptions) -> Option<String> {
+    if args.on_success == OnSuccess::Keep {
+        return None;
+    }
+    if written == 0 {
+        warn!("没有写出任何帧，保留源视频: {}", video_path.display());
+        return None;
+    }
+    if is_url(video_path) {
+        debug!("URL 不是本地文件，忽略 --on-success: {}", video_path.display());
+        return None;
+    }
+    let applied = match &args.on_success {
+        OnSuccess::Keep => return None,
+        OnSuccess::Move(dir) => move_source(video_path, dir, args).map(|target| {
+            info!("已移动源视频: {} -> {}", video_path.display(), target.display());
+            format!("moved:{}", target.to_string_lossy())
+        }),
+        OnSuccess::Delete => std::fs::remove_file(video_path)
+            .with_context(|| format!("删除源视频失败: {:?}", video_path))
+            .map(|()| {
+                info!("已删除源视频: {}", video_path.display());
+                "deleted".to_string()
+            }),
+    };
+    applied.map_err(|e| warn!("{:#}", e)).ok()
+}
+
+// 把源视频移动到 dir 下与输入目录相同的相对位置，返回实际的目标路径。
+// 先以 create_new 占住目标文件名，重名 (包括并行处理的其他视频) 时追加序号；
+// 跨文件系统无法重命名时复制后删除源文件
+fn move_source(video_path: &Path, dir: &Path, args: &ExtractOptions) -> Result<PathBuf> {
+    let relative = match root_for(video_path, args) {
+        Some((_, relative)) => relative,
+        None => PathBuf::from(video_path.file_name().context("无效的文件名")?),
+    };
+    let wanted = dir.join(relative);
+    if let Some(parent) = wanted.parent() {
+        ensure_dir(parent)?;
+    }
+    let stem = wanted.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let ext = wanted
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut target = wanted.clone();
+    let mut duplicate = 1;
+    loop {
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&target) {
+            Ok(_) => break,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                duplicate += 1;
+                target = wanted.with_file_name(format!("{}_{}{}", stem, duplicate, ext));
+            }
+            Err(e) => return Err(e).with_context(|| format!("创建目标文件失败: {:?}", target)),
+        }
+    }
+
+    if std::fs::rename(video_path, &target).is_ok() {
+        return Ok(target);
+    }
+    let copied = std::fs::copy(video_path, &target)
+        .with_context(|| format!("复制源视频失败: {:?} -> {:?}", video_path, target));
+    if let Err(e) = copied {
+        let _ = std::fs::remove_file(&target);
+        return Err(e);
+    }
+    std::fs::remove_file(video_path)
+        .with_context(|| format!("已复制到 {:?}，但删除源视频失败: {:?}", target, video_path))?;
+    Ok(target)
+}
+
+// --archive 时视频的归档文件：输出目录路径加上归档扩展名，如 <输出目录>/clip.zip
+pub(crate) fn archive_path(output_dir: &Path, format: ArchiveFormat) -> PathBuf {
+    let mut path = output_dir.as_os_str().to_os_string();
+    path.push(".");
+    path.push(format.extension());
+    PathBuf::from(path)
+}
+
+// 写入中的归档文件名后缀，完整写出后才重命名为最终的归档
+const ARCHIVE_PARTIAL_SUFFIX: &str = ".partial";
+
+// 把输出目录中的文件 (完成标记除外) 打包为归档后删除输出目录，返回归档路径。
+// 先写 <归档>.partial 再重命名，中断时留下的文件不会被当作已完成的归档
+pub(crate) fn write_archive(output_dir: &Path, format: ArchiveFormat, args: &ExtractOptions) -> Result<PathBuf> {
+    let archive = archive_path(output_dir, format);
+    let mut partial = archive.as_os_str().to_os_string();
+    partial.push(ARCHIVE_PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    let mut files = Vec::new();
+    for entry in WalkDir::new(output_dir).min_depth(1).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("读取目录失败: {:?}", output_dir))?;
+        if !entry.file_type().is_file() || entry.file_name() == DONE_MARKER {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(output_dir).unwrap_or(entry.path());
+        // 归档中统一使用 / 分隔
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((entry.into_path(), name));
+    }
+
+    let file = std::fs::File::create(&partial)
+        .with_context(|| format!("创建归档失败: {:?}", partial))?;
+    let written = match format {
+        ArchiveFormat::Zip => {
+            let compression = args.archive_compression.unwrap_or(ArchiveCompression::Stored);
+            write_zip(file, &files, compression)
+        }
+        ArchiveFormat::Tar => write_tar(file, &files),
+    };
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e.context(format!("写入归档失败: {:?}", partial)));
+    }
+    std::fs::rename(&partial, &archive)
+        .with_context(|| format!("重命名归档失败: {:?}", archive))?;
+    std::fs::remove_dir_all(output_dir)
+        .with_context(|| format!("删除已打包的输出目录失败: {:?}", output_dir))?;
+    debug!("已打包 {} 个文件: {}", files.len(), archive.display());
+    Ok(archive)
+}
+
+fn write_zip(
+    file: std::fs::File,
+    files: &[(PathBuf, String)],
+    compression: ArchiveCompression,
+) -> Result<()> {
+    let method = match compression {
+        ArchiveCompression::Stored => zip::CompressionMethod::Stored,
+        ArchiveCompression::Deflate => zip::CompressionMethod::Deflated,
+    };
+    let options = zip::write::SimpleFileOptions::default().compression_method(method);
+    let mut zip = zip::ZipWriter::new(std::io::BufWriter::new(file));
+    for (path, name) in files {
+        zip.start_file(name.as_str(), options)?;
+        let mut source =
+            std::fs::File::open(path).with_context(|| format!("读取文件失败: {:?}", path))?;
+        std::io::copy(&mut source, &mut zip)?;
+    }
+    let file = zip.finish()?.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn write_tar(file: std::fs::File, files: &[(PathBuf, String)]) -> Result<()> {
+    let mut tar = tar::Builder::new(std::io::BufWriter::new(file));
+    for (path, name) in files {
+        tar.append_path_with_name(path, name)
+            .with_context(|| format!("读取文件失败: {:?}", path))?;
+    }
+    let file = tar.into_inner()?.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+// 已有归档中的关键帧数，跳过时用于汇总
+pub(crate) fn count_archived_frames(
+    archive: &Path,
+    format: ArchiveFormat,
+    pattern: Option<&FramePattern>,
+) -> Result<usize> {
+    let file =
+        std::fs::File::open(archive).with_context(|| format!("读取归档失败: {:?}", archive))?;
+    let count = match format {
+        ArchiveFormat::Zip => zip::ZipArchive::new(file)
+            .with_context(|| format!("读取归档失败: {:?}", archive))?
+            .file_names()
+            .filter(|name| is_keyframe_file(name, pattern))
+            .count(),
+        ArchiveFormat::Tar => {
+            let mut count = 0;
+            for entry in tar::Archive::new(file).entries()? {
+                let entry = entry.with_context(|| format!("读取归档失败: {:?}", archive))?;
+                let name = entry.path()?.file_name().map(|n| n.to_string_lossy().into_owned());
+                if name.is_some_and(|name| is_keyframe_file(&name, pattern)) {
+                    count += 1;
+                }
+            }
+            count
+        }
+    };
+    Ok(count)
+}
+
+// --poster-only 的海报图路径：输出目录路径加上图片扩展名
+pub(crate) fn poster_path(output_dir: &Path, args: &ExtractOptions) -> PathBuf {
+    let mut name = output_dir.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", args.format.extension()));
+    output_dir.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_args as args;
+
+    #[test]
+    fn later_batches_collide_with_earlier_outputs() {
+        let first = PathBuf::from("/videos/cam1/clip.mp4");
+        let second = PathBuf::from("/videos/cam2/clip.mp4");
+
+        let suffix = args(&["--flatten"]);
+        let mut plan = OutputPlan::default();
+        let (kept, _) = resolve_collisions(vec![first.clone()], &suffix, &mut plan).unwrap();
+        assert_eq!(kept, [first.as_path()]);
+        let (kept, skipped) = resolve_collisions(vec![second.clone()], &suffix, &mut plan).unwrap();
+        assert_eq!(kept, [second.as_path()]);
+        assert!(skipped.is_empty());
+        assert_eq!(output_dir_for(&first, &suffix, &plan).unwrap(), Path::new("/out/clip"));
+        assert_ne!(output_dir_for(&second, &suffix, &plan).unwrap(), Path::new("/out/clip"));
+        // 再次出现的同一视频仍使用原来的目录
+        resolve_collisions(vec![first.clone()], &suffix, &mut plan).unwrap();
+        assert!(!plan.renamed.contains_key(&first));
+
+        let skip = args(&["--flatten", "--on-collision", "skip"]);
+        let mut plan = OutputPlan::default();
+        resolve_collisions(vec![first.clone()], &skip, &mut plan).unwrap();
+        let (kept, skipped) = resolve_collisions(vec![second.clone()], &skip, &mut plan).unwrap();
+        assert!(kept.is_empty());
+        assert_eq!(skipped[0].path, second);
+
+        let error = args(&["--flatten", "--on-collision", "error"]);
+        let mut plan = OutputPlan::default();
+        resolve_collisions(vec![first], &error, &mut plan).unwrap();
+        assert!(resolve_collisions(vec![second], &error, &mut plan).is_err());
+    }
+
+    #[test]
+    fn moved_keyframes_replace_existing_files() {
+        let dir = std::env::temp_dir().join(format!("vid-move-{}", std::process::id()));
+        let (from, to) = (dir.join("tmp"), dir.join("out"));
+        ensure_dir(&from).unwrap();
+        ensure_dir(&to).unwrap();
+        std::fs::write(from.join("keyframe_00001.jpg"), b"new").unwrap();
+        std::fs::write(from.join("manifest.json"), b"{}").unwrap();
+        std::fs::write(to.join("keyframe_00001.jpg"), b"old").unwrap();
+        std::fs::write(to.join("notes.txt"), b"kept").unwrap();
+
+        move_keyframes(&from, &to).unwrap();
+        assert!(!from.exists());
+        assert_eq!(std::fs::read(to.join("keyframe_00001.jpg")).unwrap(), b"new");
+        assert!(to.join("manifest.json").is_file());
+        assert!(to.join("notes.txt").is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+// 提取后对帧的处理：去重、黑帧和模糊帧检测、清晰度评分和联系表
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::path::Path;
+
+use crate::api::ExtractOptions;
+use crate::args::SheetLayout;
+use crate::extract::{DuplicateFrame, FrameEntry};
+use crate::report::csv_field;
+
+// 从 len 个元素中均匀选出 count 个的下标：count 不小于 2 时包含首尾两个，只选一个时取中间的，
+// count 不小于 len 时全选
+fn sample_evenly(len: usize, count: usize) -> Vec<usize> {
+    match count {
+        _ if count >= len => (0..len).collect(),
+        0 => Vec::new(),
+        1 => vec![len / 2],
+        _ => (0..count).map(|i| i * (len - 1) / (count - 1)).collect(),
+    }
+}
+
+// 缩略图总览的文件名
+pub(crate) const CONTACT_SHEET: &str = "sheet.jpg";
+
+// 把关键帧缩放后按网格拼成一张 JPEG，每格左下角标注时间；没有帧时不生成，返回 None。
+// 先按 --sheet-max-frames 取前 N 帧，指定了行数且帧数超过格数时再均匀抽取
+pub(crate) fn write_contact_sheet(
+    dir: &Path,
+    frames: &[FrameEntry],
+    layout: SheetLayout,
+    args: &ExtractOptions,
+) -> Result<Option<&'static str>> {
+    const GAP: u32 = 4;
+    let frames = &frames[..frames.len().min(args.sheet_max_frames.unwrap_or(usize::MAX))];
+    let frames: Vec<&FrameEntry> = match layout.rows {
+        Some(rows) => sample_evenly(frames.len(), (layout.cols * rows) as usize)
+            .into_iter()
+            .map(|i| &frames[i])
+            .collect(),
+        None => frames.iter().collect(),
+    };
+    let cols = layout.cols;
+    let Some(first) = frames.first() else {
+        return Ok(None);
+    };
+    let first_path = dir.join(&first.filename);
+    let (width, height) =
+        image::image_dimensions(&first_path).with_context(|| format!("读取帧失败: {:?}", first_path))?;
+    let tile_w = args.sheet_tile_width;
+    let tile_h = (u64::from(tile_w) * u64::from(height) / u64::from(width.max(1))).max(1) as u32;
+    let cols = cols.min(frames.len() as u32);
+    let rows = (frames.len() as u32).div_ceil(cols);
+    let mut sheet = image::RgbImage::from_pixel(
+        cols * tile_w + (cols + 1) * GAP,
+        rows * tile_h + (rows + 1) * GAP,
+        image::Rgb([32, 32, 32]),
+    );
+
+    for (i, frame) in frames.iter().enumerate() {
+        let path = dir.join(&frame.filename);
+        let tile = image::open(&path).with_context(|| format!("读取帧失败: {:?}", path))?;
+        let mut tile = image::imageops::resize(
+            &tile.to_rgb8(),
+            tile_w,
+            tile_h,
+            image::imageops::FilterType::Triangle,
+        );
+        if let Some(pts) = frame.pts_seconds {
+            draw_label(&mut tile, &format_timestamp(pts));
+        }
+        let (col, row) = (i as u32 % cols, i as u32 / cols);
+        let x = GAP + col * (tile_w + GAP);
+        let y = GAP + row * (tile_h + GAP);
+        image::imageops::replace(&mut sheet, &tile, i64::from(x), i64::from(y));
+    }
+
+    let path = dir.join(CONTACT_SHEET);
+    sheet
+        .save_with_format(&path, image::ImageFormat::Jpeg)
+        .with_context(|| format!("写入缩略图总览失败: {:?}", path))?;
+    Ok(Some(CONTACT_SHEET))
+}
+
+// 时间标签：不足一小时为 MM:SS，否则为 H:MM:SS
+pub(crate) fn format_timestamp(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{:02}:{:02}", m, s)
+    }
+}
+
+// 5x7 点阵字形 (每行低5位，高位在左)，只包含时间标签用到的字符
+fn glyph(c: char) -> Option<[u8; 7]> {
+    Some(match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        _ => return None,
+    })
+}
+
+// 在图片左下角画黑底白字的标签，字形放大2倍；图片太小放不下时不画
+fn draw_label(image: &mut image::RgbImage, text: &str) {
+    const SCALE: u32 = 2;
+    const PAD: u32 = 3;
+    let advance = 6 * SCALE;
+    let label_w = text.chars().count() as u32 * advance + 2 * PAD;
+    let label_h = 7 * SCALE + 2 * PAD;
+    if label_w > image.width() || label_h > image.height() {
+        return;
+    }
+    let top = image.height() - label_h;
+    for y in top..image.height() {
+        for x in 0..label_w {
+            image.put_pixel(x, y, image::Rgb([0, 0, 0]));
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else { continue };
+        let left = PAD + i as u32 * advance;
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..5u32 {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..SCALE {
+                    for dx in 0..SCALE {
+                        let x = left + col * SCALE + dx;
+                        let y = top + PAD + row as u32 * SCALE + dy;
+                        image.put_pixel(x, y, image::Rgb([255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 写入 timestamps.csv (filename,seconds)，无法获取时间的帧留空
+pub(crate) fn write_timestamps_csv(dir: &Path, frames: &[FrameEntry]) -> Result<()> {
+    let mut content = String::from("filename,seconds\n");
+    for frame in frames {
+        let seconds = frame.pts_seconds.map(|pts| format!("{:.3}", pts)).unwrap_or_default();
+        content.push_str(&format!("{},{}\n", csv_field(&frame.filename), seconds));
+    }
+    let path = dir.join("timestamps.csv");
+    std::fs::write(&path, content).with_context(|| format!("写入时间戳失败: {:?}", path))
+}
+
+// 按顺序比较相邻帧的感知哈希，删除与上一保留帧汉明距离小于 threshold 的帧并记入该保留帧的 duplicates，
+// 其余帧保持原文件名和序号 (不重新编号)
+pub(crate) fn dedup_frames(dir: &Path, frames: &mut Vec<FrameEntry>, threshold: u32) -> Result<()> {
+    let mut last_kept: Option<u64> = None;
+    let mut kept: Vec<FrameEntry> = Vec::with_capacity(frames.len());
+    for frame in frames.drain(..) {
+        let path = dir.join(&frame.filename);
+        let hash = dhash(&path)?;
+        let duplicate = last_kept
+            .map(|last| (last ^ hash).count_ones())
+            .filter(|&distance| distance < threshold);
+        if let (Some(distance), Some(target)) = (duplicate, kept.last_mut()) {
+            std::fs::remove_file(&path).with_context(|| format!("删除重复帧失败: {:?}", path))?;
+            target.duplicates.push(DuplicateFrame {
+                filename: frame.filename,
+                pts_seconds: frame.pts_seconds,
+                distance,
+            });
+            continue;
+        }
+        last_kept = Some(hash);
+        kept.push(frame);
+    }
+    *frames = kept;
+    Ok(())
+}
+
+// 读取帧文件并计算 gray_dhash
+fn dhash(path: &Path) -> Result<u64> {
+    let image = image::open(path).with_context(|| format!("读取帧失败: {:?}", path))?;
+    Ok(gray_dhash(&image.to_luma8()))
+}
+
+// 差值哈希 (dHash)：缩放为 9x8 灰度图，每行相邻像素比较亮度得到 64 位
+fn gray_dhash(gray: &image::GrayImage) -> u64 {
+    let small = image::imageops::resize(
+        gray,
+        9,
+        8,
+        image::imageops::FilterType::Triangle,
+    );
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+// 删除黑帧 (亮度不超过 --black-threshold 的像素占比达到 --black-ratio)，返回删除数；
+// 其余帧保持原文件名和序号。各帧在当前 rayon 线程池中并行判断
+pub(crate) fn drop_black_frames(dir: &Path, frames: &mut Vec<FrameEntry>, args: &ExtractOptions) -> Result<usize> {
+    let black: Vec<bool> = frames
+        .par_iter()
+        .map(|frame| {
+            let path = dir.join(&frame.filename);
+            let image = image::open(&path).with_context(|| format!("读取帧失败: {:?}", path))?;
+            Ok(black_ratio(&image.to_luma8(), args.black_threshold) >= args.black_ratio)
+        })
+        .collect::<Result<_>>()?;
+    let before = frames.len();
+    let mut kept = Vec::with_capacity(before);
+    for (frame, black) in frames.drain(..).zip(black) {
+        if black {
+            let path = dir.join(&frame.filename);
+            std::fs::remove_file(&path).with_context(|| format!("删除黑帧失败: {:?}", path))?;
+            continue;
+        }
+        kept.push(frame);
+    }
+    *frames = kept;
+    Ok(before - frames.len())
+}
+
+// 亮度不超过 threshold 的像素占比
+pub(crate) fn black_ratio(gray: &image::GrayImage, threshold: u8) -> f64 {
+    let total = gray.pixels().len();
+    if total == 0 {
+        return 0.0;
+    }
+    let black = gray.pixels().filter(|pixel| pixel[0] <= threshold).count();
+    black as f64 / total as f64
+}
+
+// 计算每帧的清晰度。在当前 rayon 线程池中并行解码，不会让单个视频的处理串行等待
+pub(crate) fn score_sharpness(dir: &Path, frames: &mut [FrameEntry]) -> Result<()> {
+    frames.par_iter_mut().try_for_each(|frame| {
+        frame.sharpness = Some(sharpness(&dir.join(&frame.filename))?);
+        Ok(())
+    })
+}
+
+// 删除清晰度低于 threshold 的帧，返回删除数；其余帧保持原文件名和序号
+pub(crate) fn drop_blurry_frames(dir: &Path, frames: &mut Vec<FrameEntry>, threshold: f64) -> Result<usize> {
+    score_sharpness(dir, frames)?;
+    let before = frames.len();
+    let mut kept = Vec::with_capacity(before);
+    for frame in frames.drain(..) {
+        if frame.sharpness.is_some_and(|score| score < threshold) {
+            let path = dir.join(&frame.filename);
+            std::fs::remove_file(&path).with_context(|| format!("删除模糊帧失败: {:?}", path))?;
+            continue;
+        }
+        kept.push(frame);
+    }
+    *frames = kept;
+    Ok(before - frames.len())
+}
+
+// 拉普拉斯方差：对灰度图做4邻域拉普拉斯卷积，取响应的方差。边缘越锐利方差越大
+pub(crate) fn sharpness(path: &Path) -> Result<f64> {
+    let image = image::open(path).with_context(|| format!("读取帧失败: {:?}", path))?;
+    Ok(laplacian_variance(&image.to_luma8()))
+}
+
+fn laplacian_variance(gray: &image::GrayImage) -> f64 {
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let at = |x: u32, y: u32| f64::from(gray.get_pixel(x, y)[0]);
+    let (mut sum, mut sum_sq, mut n) = (0.0, 0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let response =
+                4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1);
+            sum += response;
+            sum_sq += response * response;
+            n += 1.0;
+        }
+    }
+    let mean = sum / n;
+    sum_sq / n - mean * mean
+}
+
+// 写入 sharpness.csv (filename,sharpness)
+pub(crate) fn write_sharpness_csv(dir: &Path, frames: &[FrameEntry]) -> Result<()> {
+    let mut content = String::from("filename,sharpness\n");
+    for frame in frames {
+        let score = frame.sharpness.map(|score| format!("{:.2}", score)).unwrap_or_default();
+        content.push_str(&format!("{},{}\n", csv_field(&frame.filename), score));
+    }
+    let path = dir.join("sharpness.csv");
+    std::fs::write(&path, content).with_context(|| format!("写入清晰度失败: {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::{list_frames, reset_dir};
+
+    // 水平方向的亮度渐变，flip 时改为从右到左变亮
+    fn gradient(flip: bool) -> image::GrayImage {
+        image::GrayImage::from_fn(64, 32, |x, _| {
+            let x = if flip { 63 - x } else { x };
+            image::Luma([(x * 4) as u8])
+        })
+    }
+
+    // 1 像素的黑白棋盘格，边缘最多
+    fn checkerboard() -> image::GrayImage {
+        image::GrayImage::from_fn(32, 32, |x, y| {
+            image::Luma([if (x + y) % 2 == 0 { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn sampling_keeps_the_first_and_last_frames() {
+        assert_eq!(sample_evenly(10, 4), [0, 3, 6, 9]);
+        assert_eq!(sample_evenly(100, 2), [0, 99]);
+        assert_eq!(sample_evenly(9, 1), [4]);
+        assert!(sample_evenly(5, 0).is_empty());
+        assert_eq!(sample_evenly(3, 8), [0, 1, 2]);
+        assert_eq!(sample_evenly(3, 3), [0, 1, 2]);
+        assert!(sample_evenly(0, 4).is_empty());
+        let picked = sample_evenly(1000, 37);
+        assert_eq!((picked[0], picked[36]), (0, 999));
+        assert!(picked.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn flat_image_has_zero_sharpness() {
+        let flat = image::GrayImage::from_pixel(32, 24, image::Luma([128]));
+        assert_eq!(laplacian_variance(&flat), 0.0);
+        assert_eq!(laplacian_variance(&image::GrayImage::new(2, 2)), 0.0);
+        assert!(laplacian_variance(&gradient(false)) < laplacian_variance(&checkerboard()));
+    }
+
+    #[test]
+    fn black_ratio_counts_pixels_at_or_below_the_threshold() {
+        assert_eq!(black_ratio(&image::GrayImage::new(16, 9), 0), 1.0);
+        assert_eq!(black_ratio(&image::GrayImage::from_pixel(16, 9, image::Luma([255])), 32), 0.0);
+        // 渐变的亮度为 0, 4, ..., 252，不超过 32 的有 9 列
+        assert_eq!(black_ratio(&gradient(false), 32), 9.0 / 64.0);
+        assert_eq!(black_ratio(&image::GrayImage::new(0, 0), 32), 0.0);
+    }
+
+    #[test]
+    fn dhash_distance_follows_image_similarity() {
+        let base = gray_dhash(&gradient(false));
+        assert_eq!(base, gray_dhash(&gradient(false)));
+
+        let mut near = gradient(false);
+        near.put_pixel(10, 10, image::Luma([near.get_pixel(10, 10)[0] + 3]));
+        assert!((base ^ gray_dhash(&near)).count_ones() <= 2);
+
+        let different = gray_dhash(&gradient(true));
+        assert!((base ^ different).count_ones() >= 32);
+    }
+
+    #[test]
+    fn duplicates_are_recorded_on_the_kept_frame() {
+        let dir = std::env::temp_dir().join(format!("vid-dedup-{}", std::process::id()));
+        reset_dir(&dir).unwrap();
+        let mut near = gradient(false);
+        near.put_pixel(10, 10, image::Luma([near.get_pixel(10, 10)[0] + 3]));
+        let images = [gradient(false), gradient(false), near, gradient(true), gradient(true)];
+        for (i, image) in images.iter().enumerate() {
+            image.save(dir.join(format!("keyframe_{:05}.png", i + 1))).unwrap();
+        }
+        let pts = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let mut frames = list_frames(&dir, &pts, None).unwrap();
+
+        dedup_frames(&dir, &mut frames, 10).unwrap();
+        let kept: Vec<&str> = frames.iter().map(|frame| frame.filename.as_str()).collect();
+        assert_eq!(kept, ["keyframe_00001.png", "keyframe_00004.png"]);
+        let merged: Vec<(&str, Option<f64>)> = frames[0]
+            .duplicates
+            .iter()
+            .map(|duplicate| (duplicate.filename.as_str(), duplicate.pts_seconds))
+            .collect();
+        assert_eq!(merged, [("keyframe_00002.png", Some(1.0)), ("keyframe_00003.png", Some(2.0))]);
+        assert_eq!(frames[0].duplicates[0].distance, 0);
+        assert_eq!(frames[1].duplicates.len(), 1);
+        assert!(!dir.join("keyframe_00002.png").exists());
+        assert!(dir.join("keyframe_00004.png").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
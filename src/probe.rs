@@ -0,0 +1,493 @@
+// 调用 ffprobe 读取视频信息，检查FFmpeg和ffprobe是否可用，以及 --probe-only 的探测报告
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::warn;
+
+use crate::api::ExtractOptions;
+use crate::args::{OutputFormat, Tonemap};
+use crate::cli::count_keyframes_probe;
+use crate::error::FailureKind;
+use crate::postprocess::format_timestamp;
+
+// ffprobe 获取的视频基本信息，探测失败时各字段为 None
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct VideoInfo {
+    pub(crate) duration: Option<f64>,
+    pub(crate) width: Option<u32>,
+    pub(crate) height: Option<u32>,
+    pub(crate) codec: Option<String>,
+    /// 把画面转正需要顺时针旋转的角度 (0/90/180/270)
+    pub(crate) rotation: Option<u32>,
+    /// 传输特性为 PQ (HDR10) 或 HLG
+    pub(crate) hdr: Option<bool>,
+}
+
+// ffprobe -of json 输出中用到的字段
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    avg_frame_rate: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    color_transfer: Option<String>,
+    #[serde(default)]
+    side_data_list: Vec<ProbeSideData>,
+    tags: Option<ProbeTags>,
+}
+
+// 显示矩阵的 rotation 为逆时针角度
+#[derive(Debug, Deserialize)]
+struct ProbeSideData {
+    rotation: Option<f64>,
+}
+
+// 旧版FFmpeg以 rotate 标签记录顺时针角度
+#[derive(Debug, Deserialize)]
+struct ProbeTags {
+    rotate: Option<String>,
+}
+
+impl ProbeStream {
+    fn rotation(&self) -> Option<u32> {
+        let clockwise = match self.side_data_list.iter().find_map(|side| side.rotation) {
+            Some(rotation) => -rotation,
+            None => self.tags.as_ref()?.rotate.as_deref()?.trim().parse().ok()?,
+        };
+        // 取最接近的90度倍数
+        Some(((clockwise / 90.0).round() as i64 * 90).rem_euclid(360) as u32)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+    size: Option<String>,
+}
+
+// --probe-only 输出的单个视频信息
+#[derive(Debug, Serialize)]
+struct ProbeReport {
+    #[serde(serialize_with = "crate::report::serialize_path_lossy")]
+    path: PathBuf,
+    codec: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    duration: Option<f64>,
+    frame_rate: Option<f64>,
+    /// 关键帧数 (遵循 --start/--end)
+    keyframes: Option<u64>,
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// 扫描时获取的文件元数据，去重、排序和大小筛选共用，每个文件只 stat 一次
+#[derive(Debug, Clone)]
+pub(crate) struct FileStat {
+    pub(crate) size: u64,
+    pub(crate) modified: Option<SystemTime>,
+    /// (设备号, inode)，仅 Unix
+    pub(crate) inode: Option<(u64, u64)>,
+}
+
+// 一批视频共用的文件元数据和 ffprobe 探测结果，由 run (监视模式下为每一批)、run_batch 和
+// extract_keyframes 各自创建，随该批处理结束释放，之后源文件的变化不会被旧的结果掩盖
+#[derive(Default)]
+pub(crate) struct MediaCache {
+    stats: Mutex<BTreeMap<PathBuf, FileStat>>,
+    info: Mutex<BTreeMap<PathBuf, VideoInfo>>,
+}
+
+impl MediaCache {
+    // 普通文件 (跟随符号链接) 的元数据，不存在或不是文件时返回 None
+    pub(crate) fn file_stat(&self, path: &Path) -> Option<FileStat> {
+        if let Some(stat) = self.stats.lock().unwrap().get(path) {
+            return Some(stat.clone());
+        }
+        let metadata = std::fs::metadata(path).ok().filter(|m| m.is_file())?;
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt;
+            Some((metadata.dev(), metadata.ino()))
+        };
+        #[cfg(not(unix))]
+        let inode = None;
+        let stat = FileStat {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            inode,
+        };
+        self.stats.lock().unwrap().insert(path.to_path_buf(), stat.clone());
+        Some(stat)
+    }
+
+    // 视频信息，同一批中每个文件只调用一次 ffprobe
+    pub(crate) fn video_info(&self, ffprobe: &Path, video_path: &Path) -> Result<VideoInfo> {
+        if let Some(info) = self.info.lock().unwrap().get(video_path) {
+            return Ok(info.clone());
+        }
+        let info = probe_video_info(ffprobe, video_path)?;
+        self.info.lock().unwrap().insert(video_path.to_path_buf(), info.clone());
+        Ok(info)
+    }
+
+    // 已探测过的视频时长，不为此调用 ffprobe
+    pub(crate) fn probed_duration(&self, video_path: &Path) -> Option<f64> {
+        self.info.lock().unwrap().get(video_path)?.duration
+    }
+
+    // 视频时长：筛选阶段探测过的直接使用，否则只读取 format=duration
+    pub(crate) fn duration(&self, ffprobe: &Path, video_path: &Path) -> Result<f64> {
+        match self.probed_duration(video_path) {
+            Some(duration) => Ok(duration),
+            None => video_duration(ffprobe, video_path),
+        }
+    }
+}
+
+// --probe-only：并行探测每个视频，打印表格；指定 --manifest 时改为写入 JSON
+pub(crate) fn print_probe_report(
+    video_paths: &[PathBuf],
+    args: &ExtractOptions,
+    manifest: Option<&Path>,
+) -> Result<()> {
+    let ffprobe = ffprobe_path(args);
+    let reports: Vec<ProbeReport> = video_paths
+        .par_iter()
+        .map(|path| match probe_video(&ffprobe, path, args) {
+            Ok(report) => report,
+            Err(e) => ProbeReport {
+                path: path.clone(),
+                codec: None,
+                width: None,
+                height: None,
+                duration: None,
+                frame_rate: None,
+                keyframes: None,
+                size: None,
+                error: Some(format!("{:#}", e)),
+            },
+        })
+        .collect();
+    let failed = reports.iter().filter(|report| report.error.is_some()).count();
+    if failed > 0 {
+        warn!("{} 个视频探测失败", failed);
+    }
+
+    if let Some(path) = manifest {
+        let json = serde_json::to_string_pretty(&reports)?;
+        return std::fs::write(path, json).with_context(|| format!("写入探测结果失败: {:?}", path));
+    }
+    let unknown = || "-".to_string();
+    println!("路径\t编码\t分辨率\t时长\t帧率\t关键帧");
+    for report in &reports {
+        if let Some(error) = &report.error {
+            println!("{}\t探测失败: {}", report.path.display(), error);
+            continue;
+        }
+        let resolution = match (report.width, report.height) {
+            (Some(width), Some(height)) => format!("{}x{}", width, height),
+            _ => unknown(),
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            report.path.display(),
+            report.codec.clone().unwrap_or_else(unknown),
+            resolution,
+            report.duration.map_or_else(unknown, format_timestamp),
+            report.frame_rate.map_or_else(unknown, |fps| format!("{:.3}", fps)),
+            report.keyframes.map_or_else(unknown, |count| count.to_string())
+        );
+    }
+    Ok(())
+}
+
+// 用 ffprobe -show_streams -show_format 读取第一个视频流和容器信息，并统计关键帧数
+fn probe_video(ffprobe: &Path, video_path: &Path, args: &ExtractOptions) -> Result<ProbeReport> {
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-show_streams", "-show_format", "-of", "json"])
+        .arg(video_path)
+        .output()
+        .context("执行ffprobe命令失败")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("ffprobe返回错误状态: {} {}", output.status, stderr.trim());
+    }
+    let probe: ProbeOutput =
+        serde_json::from_slice(&output.stdout).context("无法解析ffprobe输出")?;
+    let stream = probe
+        .streams
+        .into_iter()
+        .find(|s| s.codec_type.as_deref() == Some("video"))
+        .context("没有视频流")?;
+    let format = probe.format;
+    Ok(ProbeReport {
+        path: video_path.to_path_buf(),
+        width: stream.width,
+        height: stream.height,
+        duration: format.as_ref().and_then(|f| f.duration.as_deref()?.parse().ok()),
+        frame_rate: stream.avg_frame_rate.as_deref().and_then(parse_frame_rate),
+        keyframes: count_keyframes_probe(ffprobe, video_path, args).ok(),
+        size: format.and_then(|f| f.size?.parse().ok()),
+        codec: stream.codec_name,
+        error: None,
+    })
+}
+
+// 解析 ffprobe 的分数形式帧率 (如 30000/1001)，0/0 表示未知
+fn parse_frame_rate(value: &str) -> Option<f64> {
+    let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+// 运行 ffmpeg -version 确认FFmpeg已安装，
+// 并确认版本不低于滤镜语法所需的最低版本 (force_divisible_by 需要 4.3)
+pub(crate) fn check_ffmpeg_available(ffmpeg: &Path) -> Result<()> {
+    const MIN_VERSION: (u32, u32) = (4, 3);
+
+    let output = Command::new(ffmpeg)
+        .arg("-version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success());
+    let Some(output) = output else {
+        anyhow::bail!(
+            "无法运行FFmpeg ({})，请安装FFmpeg并加入PATH，或通过 --ffmpeg-path / FFMPEG_PATH 指定路径",
+            ffmpeg.display()
+        );
+    };
+
+    // 自行编译的 git 版本 (如 N-12345-gabcdef) 无法判断版本号，直接放行
+    let banner = String::from_utf8_lossy(&output.stdout);
+    if let Some(version) = parse_ffmpeg_version(&banner) {
+        if version < MIN_VERSION {
+            anyhow::bail!(
+                "FFmpeg版本过低 ({}.{})，需要 {}.{} 或更高版本: {}",
+                version.0,
+                version.1,
+                MIN_VERSION.0,
+                MIN_VERSION.1,
+                ffmpeg.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+// 从 "ffmpeg version 6.1.1 ..." 或 "ffmpeg version n6.1 ..." 中解析主次版本号
+fn parse_ffmpeg_version(banner: &str) -> Option<(u32, u32)> {
+    let version = banner
+        .split_whitespace()
+        .skip_while(|word| *word != "version")
+        .nth(1)?;
+    let version = version.strip_prefix('n').unwrap_or(version);
+    let mut parts = version.split(['.', '-']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some((major, minor))
+}
+
+// 运行 ffprobe -version 确认ffprobe可用
+pub(crate) fn check_ffprobe_available(ffprobe: &Path) -> Result<()> {
+    let available = Command::new(ffprobe)
+        .arg("-version")
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !available {
+        anyhow::bail!(
+            "无法运行ffprobe ({})，预检需要ffprobe；可加 --no-validate 跳过预检",
+            ffprobe.display()
+        );
+    }
+    Ok(())
+}
+
+// 确认FFmpeg支持所选输出格式的编码器
+pub(crate) fn check_encoder(ffmpeg: &Path, format: OutputFormat) -> Result<()> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-encoders"])
+        .output()
+        .context(FailureKind::Spawn)?;
+    let encoders = String::from_utf8_lossy(&output.stdout);
+    let has_encoder = |name: &str| {
+        encoders
+            .lines()
+            .any(|line| line.split_whitespace().nth(1) == Some(name))
+    };
+    if !has_encoder(format.encoder()) {
+        let supported: Vec<&str> = OutputFormat::value_variants()
+            .iter()
+            .filter(|f| has_encoder(f.encoder()))
+            .map(|f| f.extension())
+            .collect();
+        anyhow::bail!(
+            "当前FFmpeg不支持 {} 编码器，无法输出 {} 格式；可用格式: {}",
+            format.encoder(),
+            format.extension(),
+            supported.join(", ")
+        );
+    }
+    Ok(())
+}
+
+// --tonemap 不为 off 时确认FFmpeg带有色调映射用到的 zscale 滤镜 (需要 libzimg)，
+// 否则 HDR 源要到提取时才失败
+pub(crate) fn check_tonemap_filter(ffmpeg: &Path, tonemap: Tonemap) -> Result<()> {
+    if tonemap == Tonemap::Off {
+        return Ok(());
+    }
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-filters"])
+        .output()
+        .context(FailureKind::Spawn)?;
+    let filters = String::from_utf8_lossy(&output.stdout);
+    if !filters.lines().any(|line| line.split_whitespace().nth(1) == Some("zscale")) {
+        anyhow::bail!(
+            "当前FFmpeg不支持 zscale 滤镜 (需要 libzimg)，无法对 HDR 源做色调映射；\
+             请更换FFmpeg或使用 --tonemap off"
+        );
+    }
+    Ok(())
+}
+
+// 使用 ffprobe 获取时长、分辨率、编码格式、旋转角度和是否为 HDR
+fn probe_video_info(ffprobe: &Path, video_path: &Path) -> Result<VideoInfo> {
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args([
+            "-show_entries",
+            "stream=codec_name,width,height,color_transfer:stream_tags=rotate\
+             :stream_side_data=rotation:format=duration",
+        ])
+        .args(["-of", "json"])
+        .arg(video_path)
+        .output()
+        .context("执行ffprobe命令失败")?;
+    if !output.status.success() {
+        anyhow::bail!("ffprobe返回错误状态: {}", output.status);
+    }
+    let probe: ProbeOutput =
+        serde_json::from_slice(&output.stdout).context("无法解析ffprobe输出")?;
+    let stream = probe.streams.into_iter().next();
+    Ok(VideoInfo {
+        duration: probe
+            .format
+            .and_then(|f| f.duration)
+            .and_then(|d| parse_duration_output(&d).ok()),
+        width: stream.as_ref().and_then(|s| s.width),
+        height: stream.as_ref().and_then(|s| s.height),
+        rotation: stream.as_ref().map(|s| s.rotation().unwrap_or(0)),
+        hdr: stream.as_ref().map(|s| {
+            matches!(s.color_transfer.as_deref(), Some("smpte2084" | "arib-std-b67"))
+        }),
+        codec: stream.and_then(|s| s.codec_name),
+    })
+}
+
+// 未指定 --ffprobe-path 时，ffprobe 默认与 ffmpeg 位于同一目录
+pub(crate) fn ffprobe_path(args: &ExtractOptions) -> PathBuf {
+    if let Some(path) = &args.ffprobe_path {
+        return path.clone();
+    }
+    match args.ffmpeg_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.join("ffprobe"),
+        _ => PathBuf::from("ffprobe"),
+    }
+}
+
+// 视频时长：视频信息中没有时长时只用 ffprobe 读取 format=duration
+pub(crate) fn known_duration(video_path: &Path, info: &VideoInfo, args: &ExtractOptions) -> Result<f64> {
+    match info.duration {
+        Some(duration) => Ok(duration),
+        None => video_duration(&ffprobe_path(args), video_path),
+    }
+}
+
+// 使用 ffprobe 获取视频时长 (秒)
+fn video_duration(ffprobe: &Path, video_path: &Path) -> Result<f64> {
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(video_path)
+        .output()
+        .context("执行ffprobe命令失败")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffprobe返回错误状态: {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse_duration_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+// 解析 ffprobe format=duration 的输出；容器未记录时长时 ffprobe 输出 N/A
+fn parse_duration_output(text: &str) -> Result<f64> {
+    let text = text.trim();
+    if text.is_empty() || text == "N/A" {
+        anyhow::bail!("ffprobe未能确定视频时长");
+    }
+    let duration: f64 = text
+        .parse()
+        .with_context(|| format!("无法解析视频时长: {:?}", text))?;
+    if !duration.is_finite() || duration <= 0.0 {
+        anyhow::bail!("无效的视频时长: {}", text);
+    }
+    Ok(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_output_is_parsed() {
+        assert_eq!(parse_duration_output("123.456000").unwrap(), 123.456);
+        assert_eq!(parse_duration_output("123.456000\n").unwrap(), 123.456);
+        assert_eq!(parse_duration_output("  7\r\n").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn missing_or_invalid_duration_is_rejected() {
+        for output in ["N/A", "N/A\n", "", "\n", "0", "0.000000\n", "-1.5", "nan", "inf", "12s"] {
+            assert!(parse_duration_output(output).is_err(), "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn rotation_is_normalized_to_clockwise_quarter_turns() {
+        let rotation =
+            |stream: &str| serde_json::from_str::<ProbeStream>(stream).unwrap().rotation();
+        // 显示矩阵为逆时针角度
+        assert_eq!(rotation(r#"{"side_data_list": [{"rotation": -90}]}"#), Some(90));
+        assert_eq!(rotation(r#"{"side_data_list": [{"rotation": 90}]}"#), Some(270));
+        assert_eq!(rotation(r#"{"side_data_list": [{"rotation": 180}]}"#), Some(180));
+        assert_eq!(rotation(r#"{"side_data_list": [{"rotation": -89.9}]}"#), Some(90));
+        // 显示矩阵优先于旧的 rotate 标签
+        assert_eq!(
+            rotation(r#"{"side_data_list": [{"rotation": -90}], "tags": {"rotate": "180"}}"#),
+            Some(90)
+        );
+        assert_eq!(rotation(r#"{"tags": {"rotate": "-90"}}"#), Some(270));
+        assert_eq!(rotation(r#"{"tags": {"rotate": "360"}}"#), Some(0));
+        assert_eq!(rotation(r#"{"tags": {"rotate": " 90 "}}"#), Some(90));
+        assert_eq!(rotation(r#"{}"#), None);
+    }
+}
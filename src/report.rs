@@ -0,0 +1,436 @@
+// 运行结果的输出：汇总清单、单视频清单、运行报告、终端汇总和 JSON 事件
+
+use anyhow::{Context, Result};
+use serde::{Serialize, Serializer};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::api::ExtractOptions;
+use crate::args::ExtractMode;
+use crate::batch::FailedVideo;
+use crate::cli::RunSummary;
+use crate::discovery::root_for;
+use crate::extract::{position_percents, DroppedFrames, FrameEntry, VideoResult};
+use crate::ffmpeg::select_filter;
+use crate::probe::VideoInfo;
+
+// 运行报告格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    pub(crate) fn from_path(path: &Path) -> Result<Self> {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            _ => anyhow::bail!("不支持的报告格式，请使用 .json 或 .csv 扩展名: {:?}", path),
+        }
+    }
+}
+
+// 运行报告中每个视频一行
+#[derive(Debug, Serialize)]
+struct ReportRow {
+    path: String,
+    /// 视频所属的输入目录，使用路径列表时为空
+    root: String,
+    status: &'static str,
+    frames: usize,
+    output_dir: String,
+    seconds: f64,
+    error: Option<String>,
+    /// 缩略图总览路径，未生成时为空
+    contact_sheet: String,
+    /// --on-success 对源视频的处理，未处理时为空
+    source_action: String,
+}
+
+// 写入每个视频输出目录的 manifest.json
+#[derive(Debug, Serialize)]
+struct VideoManifest<'a> {
+    #[serde(serialize_with = "serialize_path_lossy")]
+    source: &'a Path,
+    #[serde(flatten)]
+    info: VideoInfo,
+    mode: ExtractMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    positions: Option<Vec<f64>>,
+    /// 实际使用的选帧滤镜，固定位置模式为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<String>,
+    /// --idr-only 时为 nokey
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skip_frame: Option<&'static str>,
+    format: &'static str,
+    quality: u8,
+    frames: &'a [FrameEntry],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contact_sheet: Option<&'static str>,
+    /// HDR 源使用的色调映射算法
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tonemap: Option<&'static str>,
+    /// 因 --skip-black 被删除的黑帧数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    black_dropped: Option<usize>,
+    /// 因低于 --min-sharpness 被删除的模糊帧数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blurry_dropped: Option<usize>,
+}
+
+// 非UTF-8路径在JSON中以替换字符输出，避免整个清单写入失败
+pub(crate) fn serialize_path_lossy<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+// 默认的汇总清单文件名，与每个视频目录下的 manifest.json 区分，
+// 避免输出根目录本身也是某个视频的输出目录时互相覆盖
+pub(crate) const RUN_MANIFEST: &str = "run-manifest.json";
+
+// 将所有视频的处理结果写入JSON清单
+pub(crate) fn write_manifest(path: &Path, results: &[VideoResult]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("创建目录失败: {:?}", parent))?;
+    }
+    let json = serde_json::to_string_pretty(results)?;
+    std::fs::write(path, json).with_context(|| format!("写入清单失败: {:?}", path))
+}
+
+// 写入运行报告，每个视频一行 (成功、跳过、失败、无效)
+pub(crate) fn write_report(path: &Path, format: ReportFormat, summary: &RunSummary, args: &ExtractOptions) -> Result<()> {
+    let mut rows: Vec<ReportRow> = summary
+        .results
+        .iter()
+        .map(|r| ReportRow {
+            path: r.source.to_string_lossy().into_owned(),
+            root: report_root(&r.source, args),
+            status: if r.skipped { "skipped" } else { "ok" },
+            frames: r.frame_count,
+            output_dir: r.output_dir.to_string_lossy().into_owned(),
+            seconds: r.elapsed.as_secs_f64(),
+            error: None,
+            contact_sheet: r
+                .contact_sheet
+                .as_ref()
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            source_action: r.source_action.clone().unwrap_or_default(),
+        })
+        .chain(summary.failures.iter().map(|f| ReportRow {
+            path: f.path.to_string_lossy().into_owned(),
+            root: report_root(&f.path, args),
+            status: "failed",
+            frames: 0,
+            output_dir: f
+                .output_dir
+                .as_ref()
+                .map(|d| d.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            seconds: f.elapsed.as_secs_f64(),
+            error: Some(f.chain.join(": ")),
+            contact_sheet: String::new(),
+            source_action: String::new(),
+        }))
+        .chain(summary.invalid.iter().map(|v| ReportRow {
+            path: v.path.to_string_lossy().into_owned(),
+            root: report_root(&v.path, args),
+            status: "invalid",
+            frames: 0,
+            output_dir: String::new(),
+            seconds: 0.0,
+            error: Some(v.reason.clone()),
+            contact_sheet: String::new(),
+            source_action: String::new(),
+        }))
+        .chain(summary.filtered.iter().map(|v| ReportRow {
+            path: v.path.to_string_lossy().into_owned(),
+            root: report_root(&v.path, args),
+            status: "filtered",
+            frames: 0,
+            output_dir: String::new(),
+            seconds: 0.0,
+            error: Some(v.reason.clone()),
+            contact_sheet: String::new(),
+            source_action: String::new(),
+        }))
+        .chain(summary.scan_errors.iter().map(|e| ReportRow {
+            path: e.path.to_string_lossy().into_owned(),
+            root: report_root(&e.path, args),
+            status: "unreadable",
+            frames: 0,
+            output_dir: String::new(),
+            seconds: 0.0,
+            error: Some(e.reason.clone()),
+            contact_sheet: String::new(),
+            source_action: String::new(),
+        }))
+        .collect();
+    rows.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let content = match format {
+        ReportFormat::Json => serde_json::to_string_pretty(&rows)?,
+        ReportFormat::Csv => {
+            let mut csv =
+                String::from("path,root,status,frames,output_dir,seconds,error,contact_sheet,source_action\n");
+            for row in &rows {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{:.3},{},{},{}\n",
+                    csv_field(&row.path),
+                    csv_field(&row.root),
+                    row.status,
+                    row.frames,
+                    csv_field(&row.output_dir),
+                    row.seconds,
+                    csv_field(row.error.as_deref().unwrap_or("")),
+                    csv_field(&row.contact_sheet),
+                    csv_field(&row.source_action),
+                ));
+            }
+            csv
+        }
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("创建目录失败: {:?}", parent))?;
+    }
+    std::fs::write(path, content).with_context(|| format!("写入报告失败: {:?}", path))
+}
+
+// 报告中视频所属的输入目录
+fn report_root(path: &Path, args: &ExtractOptions) -> String {
+    root_for(path, args).map_or_else(String::new, |(root, _)| root.to_string_lossy().into_owned())
+}
+
+// 按 RFC 4180 转义CSV字段
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// 打印每个视频提取的帧数及总计
+pub(crate) fn print_summary(summary: &RunSummary, args: &ExtractOptions) {
+    let results = &summary.results;
+    if !results.is_empty() {
+        println!("\n提取结果:");
+    }
+    for result in results {
+        let capped = args
+            .max_frames
+            .is_some_and(|max| result.frame_count as u64 >= max);
+        let note = if result.skipped {
+            " (已存在，跳过)"
+        } else if result.hwaccel_fallback {
+            " (硬件解码失败，已改用软件解码)"
+        } else if capped {
+            " (已达上限)"
+        } else {
+            ""
+        };
+        println!("  {}: {} 帧{}", result.source.display(), result.frame_count, note);
+    }
+    if !summary.invalid.is_empty() {
+        println!("\n预检未通过:");
+        for invalid in &summary.invalid {
+            println!("  {}: {}", invalid.path.display(), invalid.reason);
+        }
+    }
+    if !summary.filtered.is_empty() {
+        println!("\n未满足筛选条件:");
+        for filtered in &summary.filtered {
+            println!("  {}: {}", filtered.path.display(), filtered.reason);
+        }
+    }
+    if !summary.scan_errors.is_empty() {
+        println!("\n无法访问 (扫描不完整):");
+        for scan_error in &summary.scan_errors {
+            println!("  {}: {}", scan_error.path.display(), scan_error.reason);
+        }
+    }
+    let fallbacks = results.iter().filter(|r| r.hwaccel_fallback).count();
+    if fallbacks > 0 {
+        println!("\n{} 个视频因硬件解码失败改用软件解码", fallbacks);
+    }
+    let retried = results.iter().filter(|r| r.attempts > 1).count();
+    if retried > 0 {
+        println!("{} 个视频经 --retries 重试后成功", retried);
+    }
+    let black_dropped: usize = results.iter().map(|r| r.black_dropped).sum();
+    if black_dropped > 0 {
+        println!("--skip-black 删除了 {} 个黑帧", black_dropped);
+    }
+    // 区分本次实际提取与因输出已存在而跳过的视频，帧数只统计本次新提取的
+    let (skipped, processed): (Vec<&VideoResult>, Vec<&VideoResult>) =
+        results.iter().partition(|r| r.skipped);
+    let total: usize = processed.iter().map(|r| r.frame_count).sum();
+    println!(
+        "共 {} 个视频 (处理 {} 个，跳过 {} 个，失败 {} 个，无效 {} 个，筛除 {} 个)，新提取 {} 帧，耗时 {:.1} 秒",
+        results.len() + summary.failures.len() + summary.invalid.len() + summary.filtered.len(),
+        processed.len(),
+        skipped.len(),
+        summary.failures.len(),
+        summary.invalid.len(),
+        summary.filtered.len(),
+        total,
+        summary.elapsed.as_secs_f64()
+    );
+    // 各视频耗时之和与总耗时之比反映并行利用率，可用于比较 --schedule 的效果
+    let busy: Duration = processed
+        .iter()
+        .map(|r| r.elapsed)
+        .chain(summary.failures.iter().map(|f| f.elapsed))
+        .sum();
+    if (!processed.is_empty() || !summary.failures.is_empty()) && !summary.elapsed.is_zero() {
+        println!(
+            "各视频耗时合计 {:.1} 秒，总耗时 {:.1} 秒，平均并行度 {:.1}",
+            busy.as_secs_f64(),
+            summary.elapsed.as_secs_f64(),
+            busy.as_secs_f64() / summary.elapsed.as_secs_f64()
+        );
+    }
+    if let Some(every) = args.every {
+        println!("抽帧间隔: 每 {} 个选中帧保留 1 帧", every);
+    }
+    if let Some(max_frames) = args.max_frames {
+        println!("帧数上限: 每个视频最多 {} 帧", max_frames);
+    }
+}
+
+// --json 时输出的事件，每个事件序列化为一行 JSON
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub(crate) enum JsonEvent<'a> {
+    /// 扫描和筛选后待处理的视频数；监视模式下每发现一批新视频输出一次
+    Discovered {
+        count: usize,
+    },
+    Started {
+        path: std::borrow::Cow<'a, str>,
+    },
+    Finished {
+        path: std::borrow::Cow<'a, str>,
+        /// ok 或 skipped (输出已存在)
+        status: &'static str,
+        frames: usize,
+        elapsed_ms: u128,
+    },
+    Failed {
+        path: std::borrow::Cow<'a, str>,
+        error: String,
+        elapsed_ms: u128,
+    },
+    Summary {
+        videos: usize,
+        processed: usize,
+        skipped: usize,
+        failed: usize,
+        invalid: usize,
+        filtered: usize,
+        /// 本次新提取的帧数
+        frames: usize,
+        elapsed_ms: u128,
+    },
+}
+
+impl<'a> JsonEvent<'a> {
+    pub(crate) fn finished(video_path: &'a Path, result: &Result<VideoResult>, elapsed: Duration) -> Self {
+        let path = video_path.to_string_lossy();
+        let elapsed_ms = elapsed.as_millis();
+        match result {
+            Ok(r) => JsonEvent::Finished {
+                path,
+                status: if r.skipped { "skipped" } else { "ok" },
+                frames: r.frame_count,
+                elapsed_ms,
+            },
+            Err(e) => JsonEvent::Failed { path, error: format!("{:#}", e), elapsed_ms },
+        }
+    }
+
+    pub(crate) fn summary(summary: &RunSummary) -> Self {
+        let results = &summary.results;
+        let skipped = results.iter().filter(|r| r.skipped).count();
+        JsonEvent::Summary {
+            videos: results.len()
+                + summary.failures.len()
+                + summary.invalid.len()
+                + summary.filtered.len(),
+            processed: results.len() - skipped,
+            skipped,
+            failed: summary.failures.len(),
+            invalid: summary.invalid.len(),
+            filtered: summary.filtered.len(),
+            frames: results.iter().filter(|r| !r.skipped).map(|r| r.frame_count).sum(),
+            elapsed_ms: summary.elapsed.as_millis(),
+        }
+    }
+
+    // 整行写入期间持有 stdout 的锁，并行的工作线程输出的行不会交错
+    pub(crate) fn emit(&self) {
+        let line = serde_json::to_string(self).expect("事件序列化不会失败");
+        let _ = writeln!(std::io::stdout().lock(), "{}", line);
+    }
+}
+
+// 按失败类别分组打印失败列表
+pub(crate) fn print_failure_summary(failures: &[FailedVideo], succeeded: usize) {
+    let mut sorted: Vec<&FailedVideo> = failures.iter().collect();
+    sorted.sort_by(|a, b| a.category.cmp(b.category).then_with(|| a.path.cmp(&b.path)));
+
+    eprintln!("\n成功 {} 个，失败 {} 个:", succeeded, failures.len());
+    let mut current = "";
+    for failed in sorted {
+        if failed.category != current {
+            current = failed.category;
+            eprintln!("[{}]", current);
+        }
+        let mut chain = failed.chain.iter();
+        eprintln!("  {}: {}", failed.path.display(), chain.next().map_or("", String::as_str));
+        for cause in chain {
+            eprintln!("    原因: {}", cause);
+        }
+    }
+}
+
+// 先写临时文件再重命名，保证 manifest.json 要么完整要么不存在
+pub(crate) fn write_video_manifest(
+    video_path: &Path,
+    info: &VideoInfo,
+    dir: &Path,
+    frames: &[FrameEntry],
+    contact_sheet: Option<&'static str>,
+    dropped: DroppedFrames,
+    args: &ExtractOptions,
+) -> Result<()> {
+    let info = info.clone();
+    let manifest = VideoManifest {
+        source: video_path,
+        tonemap: info.hdr.and_then(|hdr| args.tonemap.algorithm(hdr)),
+        info,
+        mode: args.mode,
+        positions: position_percents(args),
+        filter: position_percents(args).is_none().then(|| select_filter(args)),
+        skip_frame: args.idr_only.then_some("nokey"),
+        format: args.format.extension(),
+        quality: args.quality.for_video(video_path),
+        frames,
+        contact_sheet,
+        black_dropped: args.skip_black.then_some(dropped.black),
+        blurry_dropped: args.min_sharpness.map(|_| dropped.blurry),
+    };
+    let path = dir.join("manifest.json");
+    let temp_path = dir.join("manifest.json.tmp");
+    std::fs::write(&temp_path, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|| format!("写入清单失败: {:?}", temp_path))?;
+    std::fs::rename(&temp_path, &path).with_context(|| format!("写入清单失败: {:?}", path))
+}
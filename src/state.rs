@@ -0,0 +1,99 @@
+// --state-file：记录已处理视频的大小和修改时间，源文件未变化时跳过
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::discovery::is_url;
+
+// 状态文件中一个源视频的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StateEntry {
+    size: u64,
+    /// 修改时间，Unix 纪元以来的纳秒数
+    mtime_ns: u64,
+    frame_count: usize,
+    /// 完成时间，Unix 纪元以来的秒数
+    completed_at: u64,
+}
+
+// --state-file 对应的已处理视频索引，键为源文件绝对路径；并行处理时通过互斥锁更新
+pub(crate) struct StateIndex {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, StateEntry>>,
+}
+
+impl StateIndex {
+    // 读取状态文件，文件不存在或指定 --rescan 时从空索引开始
+    pub(crate) fn load(path: &Path, rescan: bool) -> Result<Self> {
+        let entries = if rescan || !path.exists() {
+            BTreeMap::new()
+        } else {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("读取状态文件失败: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("解析状态文件失败: {:?}", path))?
+        };
+        Ok(StateIndex {
+            path: path.to_path_buf(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    fn key(video_path: &Path) -> String {
+        std::path::absolute(video_path)
+            .unwrap_or_else(|_| video_path.to_path_buf())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // 源文件当前的 (大小, 修改时间纳秒)
+    fn fingerprint(video_path: &Path) -> Result<(u64, u64)> {
+        let metadata = std::fs::metadata(video_path)
+            .with_context(|| format!("读取文件信息失败: {:?}", video_path))?;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok((metadata.len(), mtime.as_nanos() as u64))
+    }
+
+    // 源文件大小和修改时间都与记录一致时返回记录的帧数
+    pub(crate) fn unchanged(&self, video_path: &Path) -> Option<usize> {
+        let (size, mtime_ns) = Self::fingerprint(video_path).ok()?;
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&Self::key(video_path))?;
+        (entry.size == size && entry.mtime_ns == mtime_ns).then_some(entry.frame_count)
+    }
+
+    pub(crate) fn record(&self, video_path: &Path, frame_count: usize) -> Result<()> {
+        // URL 没有可比较的大小和修改时间，不记录
+        if is_url(video_path) {
+            return Ok(());
+        }
+        let (size, mtime_ns) = Self::fingerprint(video_path)?;
+        let entry = StateEntry {
+            size,
+            mtime_ns,
+            frame_count,
+            completed_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        self.entries.lock().unwrap().insert(Self::key(video_path), entry);
+        Ok(())
+    }
+
+    // 先写临时文件再重命名，避免中途退出留下损坏的状态文件
+    pub(crate) fn save(&self) -> Result<()> {
+        let content = serde_json::to_string_pretty(&*self.entries.lock().unwrap())?;
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("创建目录失败: {:?}", parent))?;
+        }
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, content)
+            .with_context(|| format!("写入状态文件失败: {:?}", temp_path))?;
+        std::fs::rename(&temp_path, &self.path)
+            .with_context(|| format!("写入状态文件失败: {:?}", self.path))
+    }
+}
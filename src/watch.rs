@@ -0,0 +1,190 @@
+// --watch：监视输入目录中新出现的视频并交给 process_batch
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+use crate::api::ExtractOptions;
+use crate::args::{Args, ProgressFormat};
+use crate::batch::{process_batch, Batch, BatchResults, FailedVideo, BUDGET_EXCEEDED};
+use crate::discovery::{input_roots, is_excluded, is_video_file, sort_videos, PathFilters};
+use crate::filter::{filter_by_metadata, validate_videos, FilteredVideo, InvalidVideo};
+use crate::output::{resolve_collisions, OutputPlan};
+use crate::probe::{ffprobe_path, MediaCache};
+use crate::report::JsonEvent;
+use crate::state::StateIndex;
+use crate::CANCELLED;
+
+// 监视输入目录，把新建或移入、且大小在 --settle-secs 内不再变化的视频交给 process_batch，
+// 筛选规则和输出目录冲突的处理与首次扫描相同。收到 Ctrl-C 后返回
+pub(crate) fn watch_input(
+    cli: &Args,
+    args: &ExtractOptions,
+    plan: &mut OutputPlan,
+    state: Option<&StateIndex>,
+    outcome: &BatchResults,
+    invalid: &mut Vec<InvalidVideo>,
+    filtered: &mut Vec<FilteredVideo>,
+) -> Result<()> {
+    let roots = input_roots(args);
+    if roots.is_empty() {
+        anyhow::bail!("--watch 需要指定输入目录");
+    }
+    let filters = PathFilters::from_args(args)?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("创建文件监视器失败")?;
+    for root in roots {
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("监视目录失败: {:?}", root))?;
+        info!("正在监视 {} 中的新视频", root.display());
+    }
+    info!("按 Ctrl-C 结束监视");
+
+    let settle = Duration::from_secs(cli.settle_secs);
+    // 待处理文件 -> (上次检查时的大小, 大小最后一次变化的时间)
+    let mut pending: HashMap<PathBuf, (u64, Instant)> = HashMap::new();
+    while !CANCELLED.load(Ordering::SeqCst) && !BUDGET_EXCEEDED.load(Ordering::SeqCst) {
+        match rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(Ok(event)) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths {
+                    let wanted = is_video_file(&path, args) && !is_excluded(&path, args, &filters);
+                    if wanted && path.is_file() {
+                        let size = std::fs::metadata(&path).map_or(0, |m| m.len());
+                        pending.insert(path, (size, Instant::now()));
+                    }
+                }
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!("监视出错: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let mut ready = Vec::new();
+        pending.retain(|path, (size, changed)| {
+            let Ok(metadata) = std::fs::metadata(path) else {
+                return false;
+            };
+            if metadata.len() != *size {
+                *size = metadata.len();
+                *changed = Instant::now();
+                true
+            } else if changed.elapsed() >= settle {
+                ready.push(path.clone());
+                false
+            } else {
+                true
+            }
+        });
+        if ready.is_empty() {
+            continue;
+        }
+        // 每批使用新的缓存，同一路径上被替换的文件会重新探测
+        let cache = MediaCache::default();
+        sort_videos(&mut ready, args.sort, &cache);
+
+        if !cli.no_validate {
+            let (valid, rejected) = validate_videos(ready, &ffprobe_path(args));
+            invalid.extend(rejected);
+            ready = valid;
+        }
+        let (kept, rejected) = filter_by_metadata(ready, args, &cache);
+        filtered.extend(rejected);
+        let json = cli.progress_format == ProgressFormat::Json;
+        let ready = plan_watched(kept, args, plan, &outcome.failures, json, filtered);
+        if ready.is_empty() {
+            continue;
+        }
+        info!("发现 {} 个新视频", ready.len());
+        if json {
+            JsonEvent::Discovered { count: ready.len() }.emit();
+        }
+        // 监视模式下单个视频失败不结束监视，错误已记入失败列表
+        let batch = Batch { plan, cache: &cache, state };
+        if let Err(e) = process_batch(&ready, cli, args, batch, outcome) {
+            error!("{:#}", e);
+        }
+        if let Some(state) = state {
+            state.save()?;
+        }
+    }
+    Ok(())
+}
+
+// 逐个检查监视到的新视频与首次扫描和之前各批的输出目录是否冲突，分配的目录记入 plan，返回可以处理的视频；
+// --on-collision error 时只把冲突的视频记为失败，不结束监视
+fn plan_watched(
+    paths: Vec<PathBuf>,
+    args: &ExtractOptions,
+    plan: &mut OutputPlan,
+    failures: &Mutex<Vec<FailedVideo>>,
+    json: bool,
+    filtered: &mut Vec<FilteredVideo>,
+) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    for path in paths {
+        match resolve_collisions(vec![path.clone()], args, plan) {
+            Ok((kept, collided)) => {
+                ready.extend(kept);
+                filtered.extend(collided);
+            }
+            Err(e) => {
+                error!("{:#}", e);
+                if json {
+                    let path = path.to_string_lossy();
+                    JsonEvent::Failed { path, error: format!("{:#}", e), elapsed_ms: 0 }.emit();
+                }
+                let failure = FailedVideo::new(&path, &e, args, plan, Duration::ZERO);
+                failures.lock().unwrap().push(failure);
+            }
+        }
+    }
+    ready
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_args as args;
+    use crate::output::output_dir_for;
+
+    #[test]
+    fn watched_videos_with_the_same_stem_get_distinct_output_dirs() {
+        let first = PathBuf::from("/videos/cam1/clip.mp4");
+        let second = PathBuf::from("/videos/cam2/clip.mp4");
+        let failures = Mutex::new(Vec::new());
+        let mut filtered = Vec::new();
+
+        // 不展平时按子目录区分，展平时第二个视频改用带哈希后缀的目录
+        for options in [&[][..], &["--flatten"]] {
+            let args = args(options);
+            let mut plan = OutputPlan::default();
+            for video in [&first, &second] {
+                let paths = vec![video.clone()];
+                let ready = plan_watched(paths, &args, &mut plan, &failures, false, &mut filtered);
+                assert_eq!(ready, [video.as_path()]);
+            }
+            let first_dir = output_dir_for(&first, &args, &plan).unwrap();
+            let second_dir = output_dir_for(&second, &args, &plan).unwrap();
+            assert_ne!(first_dir, second_dir, "{:?}", options);
+        }
+        assert!(failures.lock().unwrap().is_empty());
+        assert!(filtered.is_empty());
+
+        // error 时冲突的视频记为失败，不交给 process_batch
+        let args = args(&["--flatten", "--on-collision", "error"]);
+        let mut plan = OutputPlan::default();
+        plan_watched(vec![first.clone()], &args, &mut plan, &failures, false, &mut filtered);
+        let paths = vec![second.clone()];
+        let ready = plan_watched(paths, &args, &mut plan, &failures, false, &mut filtered);
+        assert!(ready.is_empty());
+        assert_eq!(failures.lock().unwrap()[0].path, second);
+    }
+}
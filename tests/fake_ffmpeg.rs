@@ -0,0 +1,115 @@
+// 以子进程运行 vid，用假的 FFmpeg/ffprobe 脚本检查失败和超时时的行为
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+fn write_script(path: &Path, body: &str) {
+    std::fs::write(path, format!("#!/bin/sh\n{}", body)).unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+// 假的 ffmpeg：通过版本和编码器检查，-filters 列出 filter，其余调用执行 extract 脚本
+fn write_ffmpeg(dir: &Path, filter: &str, extract: &str) {
+    write_script(
+        &dir.join("ffmpeg"),
+        &format!(
+            "case \"$*\" in\n\
+             *-version*) echo 'ffmpeg version 6.1'; exit 0;;\n\
+             *-encoders*) printf ' V....D mjpeg  MJPEG\\n V....D png  PNG\\n'; exit 0;;\n\
+             *-filters*) echo ' ... {}  V->V  filter'; exit 0;;\n\
+             esac\n{}",
+            filter, extract
+        ),
+    );
+}
+
+// 在独立的临时目录中准备一个输入视频、假的 ffmpeg 和 ffprobe (时长 10 秒)
+fn setup(name: &str, extract: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vid-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("in")).unwrap();
+    std::fs::write(dir.join("in/clip.mp4"), b"video").unwrap();
+    write_ffmpeg(&dir, "zscale", extract);
+    write_script(
+        &dir.join("ffprobe"),
+        "case \"$*\" in\n\
+         *-version*) echo 'ffprobe version 6.1'; exit 0;;\n\
+         *json*) echo '{\"streams\":[{}],\"format\":{\"duration\":\"10.0\"}}';;\n\
+         *) echo '10.0';;\n\
+         esac\n",
+    );
+    dir
+}
+
+fn run_vid(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vid"))
+        .arg("--input")
+        .arg(dir.join("in"))
+        .arg("--output")
+        .arg(dir.join("out"))
+        .arg("--ffmpeg-path")
+        .arg(dir.join("ffmpeg"))
+        .arg("--ffprobe-path")
+        .arg(dir.join("ffprobe"))
+        .args(["--progress-format", "json", "--no-validate"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn events(output: &Output) -> Vec<serde_json::Value> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[test]
+fn fail_fast_still_ends_with_the_summary() {
+    let dir = setup("fail-fast", "echo 'boom' >&2\nexit 1\n");
+    let output = run_vid(&dir, &["--retries", "0"]);
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let events = events(&output);
+    let last = events.last().unwrap();
+    assert_eq!(last["event"], "summary", "{:?}", events);
+    assert_eq!(last["failed"], 1);
+    assert!(events.iter().any(|event| event["event"] == "failed"));
+    assert!(dir.join("out/run-manifest.json").is_file());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn timeout_is_shared_across_retries_and_segments() {
+    // 每次运行 0.7 秒后失败：若每次重试或每个片段各自计时，6 次尝试要 4 秒以上
+    let dir = setup("deadline", "sleep 0.7\nexit 1\n");
+    for extra in [&[][..], &["--segment-parallel", "2", "--segment-min-secs", "1"]] {
+        let started = Instant::now();
+        let args = [&["--timeout", "1", "--retries", "5", "--threads", "2"], extra].concat();
+        let output = run_vid(&dir, &args);
+        let elapsed = started.elapsed();
+        assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(elapsed < Duration::from_secs(3), "{:?}: {:?}", extra, elapsed);
+
+        let events = events(&output);
+        let failed = events.iter().find(|event| event["event"] == "failed").unwrap();
+        assert!(failed["error"].as_str().unwrap().contains("超过 1 秒"), "{:?}", failed);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_zscale_is_a_setup_error_unless_tonemapping_is_off() {
+    let dir = setup("zscale", "exit 1\n");
+    write_ffmpeg(&dir, "scale", "exit 1\n");
+    let output = run_vid(&dir, &[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("zscale"));
+    assert!(events(&output).is_empty());
+
+    // 关闭色调映射后不再检查，照常处理 (这里的 ffmpeg 总是失败)
+    let output = run_vid(&dir, &["--tonemap", "off", "--retries", "0"]);
+    assert_eq!(output.status.code(), Some(1));
+    std::fs::remove_dir_all(&dir).unwrap();
+}